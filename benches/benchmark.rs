@@ -8,11 +8,75 @@ fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("histogram");
     for size in [10usize,20,40,60,80,100].iter(){
         let mut histogram = Histogram::new(*size);
-        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &_size| {
             b.iter(|| histogram.add(rng.gen::<f64>()));
         });
     }
     group.finish();
+
+    let mut group = c.benchmark_group("histogram_buffered");
+    for size in [10usize,20,40,60,80,100].iter(){
+        let mut histogram = Histogram::new(*size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &_size| {
+            let mut buffered = histogram.buffered();
+            b.iter(|| buffered.add(rng.gen::<f64>()));
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("histogram_merge_slack");
+    for slack in [0usize,8,16,32,64,128].iter(){
+        let mut histogram = Histogram::builder().max_bins(100).merge_slack(*slack).build();
+        group.bench_with_input(BenchmarkId::from_parameter(slack), slack, |b, &_slack| {
+            b.iter(|| histogram.add(rng.gen::<f64>()));
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("histogram_add_slice");
+    let values: Vec<f64> = (0..1000).map(|_| rng.gen::<f64>()).collect();
+    for size in [20usize,100].iter(){
+        group.bench_with_input(BenchmarkId::new("add", size), size, |b, &size| {
+            b.iter(|| {
+                let mut histogram = Histogram::new(size);
+                for v in values.iter() {
+                    histogram.add(*v);
+                }
+                histogram
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("add_slice", size), size, |b, &size| {
+            b.iter(|| {
+                let mut histogram = Histogram::new(size);
+                histogram.add_slice(&values);
+                histogram
+            });
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("histogram_tracked_percentiles");
+    for slack in [0usize,100].iter(){
+        let mut histogram = Histogram::builder().max_bins(100).merge_slack(*slack).build();
+        group.bench_with_input(BenchmarkId::new("quantile", slack), slack, |b, &_slack| {
+            b.iter(|| {
+                histogram.add(rng.gen::<f64>());
+                [histogram.quantile(0.5), histogram.quantile(0.9), histogram.quantile(0.99)]
+            });
+        });
+        let mut histogram = Histogram::builder()
+            .max_bins(100)
+            .merge_slack(*slack)
+            .track_percentiles(true)
+            .build();
+        group.bench_with_input(BenchmarkId::new("tracked", slack), slack, |b, &_slack| {
+            b.iter(|| {
+                histogram.add(rng.gen::<f64>());
+                histogram.tracked_percentiles()
+            });
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
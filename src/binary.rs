@@ -0,0 +1,243 @@
+//! The binary format of a histogram, all numbers are little endian:
+//!
+//! | field      | type               |
+//! |------------|--------------------|
+//! | magic      | `b"SHST"`          |
+//! | version    | u8, currently 2    |
+//! | flags      | u8, since version 2|
+//! | max_bins   | u64                |
+//! | total      | u64                |
+//! | merges     | u64                |
+//! | min, max   | f64, NaN when empty|
+//! | bin number | u64                |
+//! | bins       | value f64, count u64, m2 f64 |
+//!
+//! Bit 0 of the flags is set when the histogram was created by `new_with_range`, only
+//! then the bins may hold zero-count anchors. Version 1 has no flags byte.
+//!
+//! Only the data is stored, options such as bounds or decay are not.
+
+use crate::{Bin, Histogram, HistogramError};
+use linked_list::LinkedList;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"SHST";
+const VERSION: u8 = 2;
+const ANCHORED: u8 = 1;
+
+impl Histogram {
+    /// to_bytes serializes the bins, `max_bins`, the total and the extremes into the
+    /// binary format read by [`Histogram::from_reader`]. Options such as bounds or
+    /// decay are not stored.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let bytes = histogram.to_bytes();
+    ///  let restored = Histogram::from_reader(&bytes[..]).unwrap();
+    ///  assert_eq!(restored.quantile(0.5), histogram.quantile(0.5));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(54 + self.bins.len() * 24);
+        self.write_to(&mut bytes)
+            .expect("writing into a vector never fails");
+        bytes
+    }
+
+    /// write_to writes the binary format of [`Histogram::to_bytes`] directly into `writer`,
+    /// field by field and bin by bin, so no intermediate buffer is allocated. Wrap unbuffered
+    /// sinks such as files or sockets in a `BufWriter`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  histogram.add(1.0);
+    ///  let mut bytes = Vec::new();
+    ///  histogram.write_to(&mut bytes).unwrap();
+    ///  assert_eq!(bytes, histogram.to_bytes());
+    /// ```
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&[if self.anchored { ANCHORED } else { 0 }])?;
+        writer.write_all(&(self.max_bins as u64).to_le_bytes())?;
+        writer.write_all(&self.total.to_le_bytes())?;
+        writer.write_all(&self.merges.to_le_bytes())?;
+        writer.write_all(&self.min.unwrap_or(f64::NAN).to_le_bytes())?;
+        writer.write_all(&self.max.unwrap_or(f64::NAN).to_le_bytes())?;
+        writer.write_all(&(self.bins.len() as u64).to_le_bytes())?;
+        for i in self.bins.iter() {
+            writer.write_all(&i.value.to_le_bytes())?;
+            writer.write_all(&(i.count as u64).to_le_bytes())?;
+            writer.write_all(&i.m2.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// from_reader parses a histogram written by [`Histogram::to_bytes`] or
+    /// [`Histogram::write_to`] from any reader,
+    /// the header is read first and then the bins one by one, so the whole serialized
+    /// histogram is never buffered. Malformed data returns [`HistogramError::InvalidFormat`]
+    /// and failing reads [`HistogramError::Io`].
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Histogram, HistogramError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(HistogramError::InvalidFormat("bad magic"));
+        }
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        let flags = match version[0] {
+            1 => 0,
+            VERSION => {
+                let mut flags = [0u8; 1];
+                reader.read_exact(&mut flags)?;
+                flags[0]
+            }
+            _ => return Err(HistogramError::InvalidFormat("unsupported version")),
+        };
+        let anchored = flags & ANCHORED != 0;
+        let max_bins = read_u64(&mut reader)?;
+        let mut histogram = Histogram::try_new(max_bins as usize)?;
+        let total = read_u64(&mut reader)?;
+        histogram.merges = read_u64(&mut reader)?;
+        let min = read_f64(&mut reader)?;
+        let max = read_f64(&mut reader)?;
+        let len = read_u64(&mut reader)?;
+
+        let mut bins = LinkedList::new();
+        let mut sum: u64 = 0;
+        let mut last = f64::NEG_INFINITY;
+        for _ in 0..len {
+            let value = read_f64(&mut reader)?;
+            let count = read_u64(&mut reader)?;
+            let m2 = read_f64(&mut reader)?;
+            if value.is_nan()
+                || value <= last
+                || (count == 0 && !anchored)
+                || count > usize::MAX as u64
+            {
+                return Err(HistogramError::InvalidFormat("bins not sorted or empty"));
+            }
+            last = value;
+            sum = sum.saturating_add(count);
+            bins.push_back(Bin {
+                value,
+                count: count as usize,
+                m2,
+            });
+        }
+        if sum != total {
+            return Err(HistogramError::InvalidFormat(
+                "counts don't match the total",
+            ));
+        }
+        if total > 0 {
+            if min.is_nan() || max.is_nan() {
+                return Err(HistogramError::InvalidFormat("missing extremes"));
+            }
+            histogram.min = Some(min);
+            histogram.max = Some(max);
+        }
+        histogram.anchored = anchored && bins.iter().any(|i| i.count == 0);
+        histogram.bins = bins;
+        histogram.total = total;
+        histogram.refresh_moments();
+        histogram.compact();
+        Ok(histogram)
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, HistogramError> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64, HistogramError> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Histogram, HistogramError};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_from_reader() {
+        let mut histogram = Histogram::new(20);
+        for i in 1..=1000 {
+            histogram.add((i % 100) as f64);
+        }
+        let mut cursor = Cursor::new(histogram.to_bytes());
+        let restored = Histogram::from_reader(&mut cursor).unwrap();
+        assert_eq!(cursor.position() as usize, cursor.get_ref().len());
+        assert_eq!(
+            restored.iter_bins().collect::<Vec<_>>(),
+            histogram.iter_bins().collect::<Vec<_>>()
+        );
+        assert_eq!(restored.min(), histogram.min());
+        assert_eq!(restored.max(), histogram.max());
+        // restored moments are recomputed from the bins, only rounding differs
+        let (restored_variance, variance) =
+            (restored.variance().unwrap(), histogram.variance().unwrap());
+        assert!((restored_variance - variance).abs() <= 1e-12 * variance);
+        assert_eq!(restored.merge_count(), histogram.merge_count());
+
+        let empty = Histogram::from_reader(Cursor::new(Histogram::new(10).to_bytes())).unwrap();
+        assert!(empty.report().is_none());
+
+        let bytes = histogram.to_bytes();
+        assert!(matches!(
+            Histogram::from_reader(&bytes[..bytes.len() - 1]),
+            Err(HistogramError::Io(_))
+        ));
+        assert!(matches!(
+            Histogram::from_reader(&b"HIST"[..]),
+            Err(HistogramError::InvalidFormat(_))
+        ));
+
+        // zero-count bins are only accepted from an anchored histogram
+        let mut anchored = Histogram::new_with_range(10, 0.0, 90.0);
+        anchored.add(42.0);
+        let bytes = anchored.to_bytes();
+        let restored = Histogram::from_reader(&bytes[..]).unwrap();
+        assert!(restored.iter_bins().eq(anchored.iter_bins()));
+        let mut unflagged = bytes.clone();
+        unflagged[5] = 0;
+        assert!(matches!(
+            Histogram::from_reader(&unflagged[..]),
+            Err(HistogramError::InvalidFormat("bins not sorted or empty"))
+        ));
+
+        // version 1 has no flags byte
+        let mut version1 = histogram.to_bytes();
+        version1[4] = 1;
+        version1.remove(5);
+        let restored = Histogram::from_reader(&version1[..]).unwrap();
+        assert!(restored.iter_bins().eq(histogram.iter_bins()));
+    }
+
+    #[test]
+    fn test_write_to() {
+        let mut histogram = Histogram::new(20);
+        for i in 1..=1000 {
+            histogram.add(i as f64 / 3.0);
+        }
+        let mut cursor = Cursor::new(Vec::new());
+        histogram.write_to(&mut cursor).unwrap();
+        cursor.set_position(0);
+        let restored = Histogram::from_reader(&mut cursor).unwrap();
+        assert_eq!(
+            restored.iter_bins().collect::<Vec<_>>(),
+            histogram.iter_bins().collect::<Vec<_>>()
+        );
+        assert_eq!(restored.quantile(0.99), histogram.quantile(0.99));
+        assert_eq!(cursor.into_inner(), histogram.to_bytes());
+    }
+}
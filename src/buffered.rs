@@ -0,0 +1,40 @@
+use crate::Histogram;
+
+/// BufferedHistogram is a faster way to add values than [`Histogram::add`] for very high
+/// ingest rates, created by [`Histogram::buffered`](crate::Histogram::buffered). Values are
+/// appended to an unsorted staging buffer, and once the buffer holds `max_bins` values it is
+/// sorted and merged into the bins in one pass, which avoids walking the bins for every value.
+/// The histogram stays mutably borrowed until the BufferedHistogram is dropped, which flushes
+/// the rest of the buffer, so every query sees all values added through it.
+pub struct BufferedHistogram<'a> {
+    pub(crate) histogram: &'a mut Histogram,
+    pub(crate) buffer: Vec<f64>,
+}
+
+impl<'a> BufferedHistogram<'a> {
+    /// add stages a value, the buffer is merged into the bins once it is full.
+    pub fn add(&mut self, number: f64) {
+        self.buffer.push(number);
+        if self.buffer.len() >= self.histogram.max_bins {
+            self.flush();
+        }
+    }
+
+    /// flush moves the staged values into the bins without waiting for a full buffer.
+    pub fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let values = std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(self.histogram.max_bins),
+        );
+        self.histogram.merge_values(values);
+    }
+}
+
+impl<'a> Drop for BufferedHistogram<'a> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,237 @@
+use crate::reservoir::Reservoir;
+use crate::top_k::TopK;
+use crate::tracker::Tracker;
+use crate::{Histogram, NanPolicy};
+
+/// HistogramBuilder collects the optional settings of a [`Histogram`] before creating it,
+/// use [`Histogram::builder`] to get one.
+/// # Examples
+///
+/// ```
+///  use crate::stream_histogram::Histogram;
+///  let histogram = Histogram::builder()
+///       .max_bins(50)
+///       .bounds(0.0, 1000.0)
+///       .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct HistogramBuilder {
+    max_bins: usize,
+    bounds: Option<(f64, f64)>,
+    ema_decay: Option<f64>,
+    tail_reserve: f64,
+    top_k: usize,
+    quantize: Option<f64>,
+    reservoir: usize,
+    merge_slack: usize,
+    nearest_bin: Option<f64>,
+    preserve_singletons: Option<f64>,
+    track_insertion_order: bool,
+    nan_policy: NanPolicy,
+    track_percentiles: bool,
+}
+
+impl Default for HistogramBuilder {
+    fn default() -> Self {
+        HistogramBuilder {
+            max_bins: 100,
+            bounds: None,
+            ema_decay: None,
+            tail_reserve: 0.0,
+            top_k: 0,
+            quantize: None,
+            reservoir: 0,
+            merge_slack: 0,
+            nearest_bin: None,
+            preserve_singletons: None,
+            track_insertion_order: false,
+            nan_policy: NanPolicy::default(),
+            track_percentiles: false,
+        }
+    }
+}
+
+impl HistogramBuilder {
+    /// max_bins sets the bucket number, see [`Histogram::new`]. Default is 100.
+    pub fn max_bins(mut self, max_bins: usize) -> Self {
+        self.max_bins = max_bins;
+        self
+    }
+
+    /// bounds clamps every added value into `lower..=upper`, see [`Histogram::with_bounds`].
+    pub fn bounds(mut self, lower: f64, upper: f64) -> Self {
+        self.bounds = Some((lower, upper));
+        self
+    }
+
+    /// ema_decay turns the histogram into an exponential moving histogram: every add ages the
+    /// counts already recorded by the factor `1 - alpha`, so the histogram follows the recent
+    /// values without calling [`Histogram::decay`] by hand. Aging every bin is O(n), so the
+    /// factors are accumulated and applied in one pass once they have halved the weight of the
+    /// recorded values, between two passes the older values weigh up to twice too much.
+    /// `alpha` must be inside `0.0..1.0`, other values disable the decay.
+    pub fn ema_decay(mut self, alpha: f64) -> Self {
+        self.ema_decay = if alpha > 0.0 && alpha < 1.0 {
+            Some(alpha)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// tail_reserve reserves a `fraction` of `max_bins`, split evenly between the bottom and
+    /// the top decile of the counts, so the tails where SLOs live keep a finer resolution than
+    /// the body. Bins inside a tail are not merged beyond a tenth of the total divided by the
+    /// reserved bins per tail, the body absorbs the merges instead. At least two bins are
+    /// always left for the body. `fraction` must be inside `0.0..=1.0`, other values disable
+    /// the reservation.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let histogram = Histogram::builder().max_bins(50).tail_reserve(0.4).build();
+    /// ```
+    pub fn tail_reserve(mut self, fraction: f64) -> Self {
+        self.tail_reserve = if (0.0..=1.0).contains(&fraction) {
+            fraction
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// top_k tracks the `k` most frequent exact values next to the bins, see
+    /// [`Histogram::top_values`]. It keeps `2 * k` counters and costs a hash lookup per
+    /// added value, 0 (the default) disables the tracking.
+    pub fn top_k(mut self, k: usize) -> Self {
+        self.top_k = k;
+        self
+    }
+
+    /// quantize snaps every added value to the nearest multiple of `step` before it is
+    /// inserted, so nearly identical readings (e.g. noisy sensor values) share one bin
+    /// instead of filling the bins with distinct values that merge poorly. The trade-off is
+    /// resolution: nothing finer than `step` can be told apart, and each value moves by up to
+    /// `step / 2`, which bounds the error of the mean and quantiles it adds. `step` must be
+    /// positive and finite, other values disable the quantization.
+    pub fn quantize(mut self, step: f64) -> Self {
+        self.quantize = Some(step).filter(|step| *step > 0.0 && step.is_finite());
+        self
+    }
+
+    /// reservoir keeps a uniform random sample of up to `capacity` raw values next to the
+    /// bins, so the exact quantiles of the sample can be compared with the binned ones, see
+    /// [`Histogram::reservoir_quantile`]. It costs `capacity` floats of memory, 0 (the
+    /// default) disables the sampling.
+    pub fn reservoir(mut self, capacity: usize) -> Self {
+        self.reservoir = capacity;
+        self
+    }
+
+    /// merge_slack lets the bins grow up to `max_bins + k` before they are merged, and then
+    /// merges them back down to `max_bins` in one batch. Once the histogram is full every add
+    /// otherwise scans the bins for the closest pair, batching amortizes that scan over `k`
+    /// adds at the cost of up to `k` extra bins of memory. A batch rebuilds the bins, so it
+    /// only pays off when `k` is about half of `max_bins` or more, see the `merge_slack`
+    /// benchmark. Default is 0, merge on every add.
+    pub fn merge_slack(mut self, k: usize) -> Self {
+        self.merge_slack = k;
+        self
+    }
+
+    /// nearest_bin adds each new value to the nearest existing bin when it is at most
+    /// `tolerance` away, instead of creating a bin for it. By default a value only shares a
+    /// bin when it is exactly equal to the bin value, so noisy values near a bin fill the bins
+    /// and force merges, while with a tolerance they are absorbed the way a classic histogram
+    /// counts a value into its bucket. The bin moves to the weighted average, so the mean and
+    /// variance stay exact, but the histogram is no longer exact (see
+    /// [`Histogram::is_exact`]). Buffered values are not snapped. `tolerance` must be positive
+    /// and finite, other values keep the exact-equality mode.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::builder().nearest_bin(0.1).build();
+    ///  histogram.add(1.0);
+    ///  histogram.add(1.05);
+    ///  assert_eq!(histogram.iter_bins().count(), 1);
+    /// ```
+    pub fn nearest_bin(mut self, tolerance: f64) -> Self {
+        self.nearest_bin = Some(tolerance).filter(|t| *t > 0.0 && t.is_finite());
+        self
+    }
+
+    /// preserve_singletons changes how a bin holding a single value is merged with a larger
+    /// neighbour at most `tolerance` away: its count is added to the neighbour at the
+    /// neighbour's value instead of moving the bin to the weighted average. Bin values then
+    /// stay values which were actually observed, so sparse tails such as p99.9 report a real
+    /// value rather than an average no request ever had. The trade-off is the mean: the moved
+    /// value shifts it by up to `tolerance / total` per merge, and the variance is no longer
+    /// exact. Merges of two single values, or beyond the tolerance, still average.
+    /// `tolerance` must be positive, other values disable the option.
+    pub fn preserve_singletons(mut self, tolerance: f64) -> Self {
+        self.preserve_singletons = Some(tolerance).filter(|t| *t > 0.0);
+        self
+    }
+
+    /// track_insertion_order keeps the first and the latest raw value added, see
+    /// [`Histogram::first_value`] and [`Histogram::last_value`], which help to check
+    /// assumptions about the order of a stream. It costs two floats and a branch per add,
+    /// so it is disabled by default.
+    pub fn track_insertion_order(mut self, enabled: bool) -> Self {
+        self.track_insertion_order = enabled;
+        self
+    }
+
+    /// nan_policy selects how NaN values are handled, see [`NanPolicy`]. Default is
+    /// [`NanPolicy::Reject`], `add` and `try_add` both ignore NaN, only
+    /// [`NanPolicy::TreatAsError`] makes `try_add` reject it.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::{Histogram, NanPolicy};
+    ///  let mut histogram = Histogram::builder().nan_policy(NanPolicy::Skip).build();
+    ///  histogram.add(f64::NAN);
+    ///  assert_eq!(histogram.nan_count(), 1);
+    /// ```
+    pub fn nan_policy(mut self, policy: NanPolicy) -> Self {
+        self.nan_policy = policy;
+        self
+    }
+
+    /// track_percentiles keeps p50, p90 and p99 up to date on every add, see
+    /// [`Histogram::tracked_percentiles`], for dashboards which read them after each value.
+    /// Between merges most adds update them in O(1), so it pays off before the histogram is
+    /// full and with [`HistogramBuilder::merge_slack`]. Disabled by default.
+    pub fn track_percentiles(mut self, enabled: bool) -> Self {
+        self.track_percentiles = enabled;
+        self
+    }
+
+    /// build creates the histogram with the collected settings.
+    pub fn build(self) -> Histogram {
+        let mut histogram = match self.bounds {
+            Some((lower, upper)) => Histogram::with_bounds(self.max_bins, lower, upper),
+            None => Histogram::new(self.max_bins),
+        };
+        histogram.ema_decay = self.ema_decay;
+        histogram.quantize = self.quantize;
+        histogram.merge_slack = self.merge_slack;
+        histogram.snap_tolerance = self.nearest_bin;
+        histogram.singleton_tolerance = self.preserve_singletons;
+        histogram.track_order = self.track_insertion_order;
+        histogram.nan_policy = self.nan_policy;
+        if self.track_percentiles {
+            histogram.tracker = Some(Tracker::default());
+        }
+        if self.top_k > 0 {
+            histogram.top_k = Some(TopK::new(self.top_k));
+        }
+        if self.reservoir > 0 {
+            histogram.reservoir = Some(Reservoir::new(self.reservoir));
+        }
+        let reserved = (self.tail_reserve * histogram.max_bins as f64 / 2.0).round() as usize;
+        histogram.tail_bins = reserved.min(histogram.max_bins.saturating_sub(2) / 2);
+        histogram
+    }
+}
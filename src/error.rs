@@ -0,0 +1,52 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// HistogramError is returned by the `try_` variants of the histogram methods,
+/// which reject invalid input instead of silently coercing or ignoring it, and
+/// by the deserialization of the binary format.
+#[derive(Debug)]
+pub enum HistogramError {
+    /// the bucket number is not usable, e.g. 0
+    InvalidMaxBins(usize),
+    /// the value can't be recorded, e.g. NaN or infinity
+    InvalidValue(f64),
+    /// the histogram holds no values
+    Empty,
+    /// the total count can't grow any further
+    Full,
+    /// reading the serialized histogram failed
+    Io(io::Error),
+    /// the serialized histogram is malformed
+    InvalidFormat(&'static str),
+}
+
+impl fmt::Display for HistogramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistogramError::InvalidMaxBins(max_bins) => {
+                write!(f, "invalid bucket number {}", max_bins)
+            }
+            HistogramError::InvalidValue(value) => write!(f, "invalid value {}", value),
+            HistogramError::Empty => write!(f, "histogram is empty"),
+            HistogramError::Full => write!(f, "histogram total count is saturated"),
+            HistogramError::Io(err) => write!(f, "io error: {}", err),
+            HistogramError::InvalidFormat(reason) => write!(f, "invalid format: {}", reason),
+        }
+    }
+}
+
+impl error::Error for HistogramError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HistogramError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HistogramError {
+    fn from(err: io::Error) -> Self {
+        HistogramError::Io(err)
+    }
+}
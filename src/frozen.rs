@@ -0,0 +1,59 @@
+use crate::{target_rank, MAX_QUANTILE};
+
+/// FrozenHistogram is the read only form of a [`Histogram`](crate::Histogram), created by
+/// [`Histogram::freeze`](crate::Histogram::freeze) once no more values will be added.
+/// The bins are stored with their cumulative counts, so queries use a binary search
+/// instead of walking the bins. The results are the same as the live histogram's.
+#[derive(Debug, Clone)]
+pub struct FrozenHistogram {
+    pub(crate) values: Vec<f64>,
+    pub(crate) cumulative: Vec<u64>,
+    pub(crate) total: u64,
+    pub(crate) min: Option<f64>,
+    pub(crate) max: Option<f64>,
+}
+
+impl FrozenHistogram {
+    /// total returns how many values have been recorded.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// min returns the smallest value recorded, None when empty.
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    /// max returns the largest value recorded, None when empty.
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    /// quantile returns the q-quantile, see [`Histogram::quantile`](crate::Histogram::quantile).
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let rank = target_rank(q, self.total)?;
+        let index = self.cumulative.partition_point(|&count| count < rank);
+        match self.values.get(index) {
+            Some(value) => Some(*value),
+            None if q <= MAX_QUANTILE => self.values.last().copied(),
+            None => None,
+        }
+    }
+
+    /// rank returns how many values are less than or equal to `x`.
+    pub fn rank(&self, x: f64) -> u64 {
+        match self.values.partition_point(|&value| value <= x) {
+            0 => 0,
+            index => self.cumulative[index - 1],
+        }
+    }
+
+    /// cdf returns the fraction of values less than or equal to `x`, see
+    /// [`Histogram::cdf`](crate::Histogram::cdf). Returns None when empty.
+    pub fn cdf(&self, x: f64) -> Option<f64> {
+        if self.total == 0 {
+            return None;
+        }
+        Some(self.rank(x) as f64 / self.total as f64)
+    }
+}
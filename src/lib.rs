@@ -1,23 +1,142 @@
 extern crate rand;
 
 use linked_list::LinkedList;
-use std::ops::Add;
+use reservoir::Reservoir;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::ops;
+use top_k::TopK;
+use tracker::Tracker;
 
-#[derive(Debug)]
+mod binary;
+mod buffered;
+mod builder;
+mod error;
+mod frozen;
+mod reservoir;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+mod top_k;
+mod tracker;
+
+pub use buffered::BufferedHistogram;
+pub use builder::HistogramBuilder;
+pub use error::HistogramError;
+pub use frozen::FrozenHistogram;
+
+#[derive(Debug, Clone)]
 struct Bin {
     value: f64,
     count: usize,
+    // sum of squared distances of the merged values to `value`, the spread
+    // inside the bin which would be lost by merging. Only the variance uses it,
+    // the quantile interpolation does not.
+    m2: f64,
+}
+
+impl Bin {
+    fn new(value: f64, count: usize) -> Bin {
+        Bin {
+            value,
+            count,
+            m2: 0.0,
+        }
+    }
+
+    // part returns a bin at `value` holding `count` of the values of this bin,
+    // with the matching share of its spread.
+    fn part(&self, value: f64, count: usize) -> Bin {
+        let share = if self.count == 0 {
+            0.0
+        } else {
+            count as f64 / self.count as f64
+        };
+        Bin {
+            value,
+            count,
+            m2: self.m2 * share,
+        }
+    }
+}
+
+// Moments keeps the running count, mean and sum of squared deviations (m2) of the
+// recorded values, so mean and variance don't have to walk the bins. Values are
+// folded in with Chan's formula, which stays accurate where a plain sum and sum of
+// squares would cancel. Merging bins keeps both the mean and m2 of the histogram,
+// only operations which move values (rescaling, removing bins) recompute them.
+#[derive(Debug, Clone, Default)]
+struct Moments {
+    count: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Moments {
+    // of computes the moments of the bins.
+    fn of<'a>(bins: impl Iterator<Item = &'a Bin> + Clone) -> Moments {
+        let (count, sum) = bins.clone().fold((0.0, 0.0), |(count, sum), i| {
+            (count + i.count as f64, sum + i.value * i.count as f64)
+        });
+        if count == 0.0 {
+            return Moments::default();
+        }
+        let mean = sum / count;
+        let m2 = bins.fold(0.0, |m2, i| {
+            m2 + i.count as f64 * (i.value - mean) * (i.value - mean) + i.m2
+        });
+        Moments { count, mean, m2 }
+    }
+
+    // add folds `count` values with the given mean and m2 into the moments.
+    fn add(&mut self, count: f64, mean: f64, m2: f64) {
+        if count == 0.0 {
+            return;
+        }
+        let total = self.count + count;
+        let delta = mean - self.mean;
+        self.mean += delta * count / total;
+        self.m2 += m2 + delta * delta * self.count * count / total;
+        self.count = total;
+    }
 }
 
 /// A Histogram struct include a double linklist and some attributes for manage data.
 /// using linkedList for fast insert and merge items in a sorted data structure
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct Histogram {
     bins: LinkedList<Bin>,
     max_bins: usize,
     total: u64,
     min: Option<f64>,
     max: Option<f64>,
+    bounds: Option<(f64, f64)>,
+    clamped: u64,
+    merges: u64,
+    timestamps: Option<(f64, f64)>,
+    timed: u64,
+    saturation_hook: Option<fn(f64)>,
+    ema_decay: Option<f64>,
+    pending_decay: f64,
+    tail_bins: usize,
+    max_total: Option<u64>,
+    top_k: Option<TopK>,
+    quantize: Option<f64>,
+    // whether a value was changed by quantize or the counts were rescaled, see is_exact
+    lossy: bool,
+    reservoir: Option<Reservoir>,
+    // whether the bins may hold zero-count anchors, see new_with_range
+    anchored: bool,
+    merge_slack: usize,
+    snap_tolerance: Option<f64>,
+    singleton_tolerance: Option<f64>,
+    moments: Moments,
+    // the first and last raw value added, tracked when track_order is set
+    track_order: bool,
+    insertion_order: Option<(f64, f64)>,
+    nan_policy: NanPolicy,
+    nan_count: u64,
+    tracker: Option<Tracker>,
 }
 
 impl Default for Histogram {
@@ -30,6 +149,11 @@ impl Histogram {
     /// for example the mean、max、min、 q-quantiles and cumulative distribution etc.
     /// You must set the bucket number first, greater bucket number will bring more precision， but also
     /// bring more load for process. Default is 10, any number less than 10 will reset to default.
+    ///
+    /// Every distinct value keeps its own bin until there are more than `max_bins` of them,
+    /// so for small samples the statistics are exact. Only when the bins overflow the closest
+    /// bins start to be merged and the histogram switches to approximation,
+    /// see [`Histogram::is_exact`].
     /// # Examples
     ///
     /// ```
@@ -44,340 +168,6453 @@ impl Histogram {
         if max_bin < 10 {
             max_bin = 10;
         };
-        Histogram {
+        Histogram::try_new(max_bin).expect("bucket number is clamped")
+    }
+
+    /// try_new creates a histogram with exactly `max_bins` buckets, unlike
+    /// [`Histogram::new`] small bucket numbers are not raised to 10, and 0 is
+    /// rejected with [`HistogramError::InvalidMaxBins`].
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  assert!(Histogram::try_new(0).is_err());
+    ///  assert!(Histogram::try_new(5).is_ok());
+    /// ```
+    pub fn try_new(max_bins: usize) -> Result<Histogram, HistogramError> {
+        if max_bins == 0 {
+            return Err(HistogramError::InvalidMaxBins(max_bins));
+        }
+        Ok(Histogram {
             bins: LinkedList::new(),
-            max_bins: max_bin,
+            max_bins,
             total: 0,
             min: None,
             max: None,
+            bounds: None,
+            clamped: 0,
+            merges: 0,
+            timestamps: None,
+            timed: 0,
+            saturation_hook: None,
+            ema_decay: None,
+            pending_decay: 1.0,
+            tail_bins: 0,
+            max_total: None,
+            top_k: None,
+            quantize: None,
+            lossy: false,
+            reservoir: None,
+            anchored: false,
+            merge_slack: 0,
+            snap_tolerance: None,
+            singleton_tolerance: None,
+            moments: Moments::default(),
+            track_order: false,
+            insertion_order: None,
+            nan_policy: NanPolicy::default(),
+            nan_count: 0,
+            tracker: None,
+        })
+    }
+
+    /// with_bounds create a histogram which clamp every value into `[lower, upper]`
+    /// before insert it, values out of range will land at the boundaries.
+    /// This is useful when the data has a physically possible range and
+    /// the spikes outside it should not pollute the tails.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::with_bounds(20, 0.0, 100.0);
+    ///  histogram.add(-5.0);
+    ///  histogram.add(50.0);
+    ///  histogram.add(1000.0);
+    ///  assert_eq!(histogram.clamped_count(), 2);
+    ///  assert_eq!(histogram.report().unwrap().max, 100.0);
+    /// ```
+    pub fn with_bounds(max: usize, lower: f64, upper: f64) -> Histogram {
+        let mut histogram = Histogram::new(max);
+        if lower <= upper {
+            histogram.bounds = Some((lower, upper));
+        } else {
+            histogram.bounds = Some((upper, lower));
         }
+        histogram
     }
 
-    /// add method update histogram data structure from stream
-    /// each time update a new value current value must be f64
-    /// after update, the inner process will check the bucket number
-    /// and merge two smallest gap bucket into one bucket.
+    /// from_uniform_buckets loads a classic fixed-width histogram produced by another tool.
+    /// Bucket `i` covers `edges[i]..edges[i + 1]` and its count is placed at the midpoint of
+    /// the bucket, empty buckets are skipped. Panics when `edges.len() != counts.len() + 1`.
     /// # Examples
     ///
     /// ```
     ///  use crate::stream_histogram::Histogram;
-    ///  let mut histogram = Histogram::new(20);
-    ///  for i in 1..=100000 {
+    ///  let histogram = Histogram::from_uniform_buckets(&[0.0, 10.0, 20.0], &[3, 1], 10);
+    ///  assert_eq!(histogram.mean(), Some(7.5));
+    /// ```
+    pub fn from_uniform_buckets(edges: &[f64], counts: &[usize], max_bins: usize) -> Histogram {
+        assert_eq!(
+            edges.len(),
+            counts.len() + 1,
+            "from_uniform_buckets expects one more edge than counts"
+        );
+        let mut histogram = Histogram::new(max_bins);
+        for (edge, &count) in edges.windows(2).zip(counts) {
+            if count > 0 {
+                histogram.add_weighted((edge[0] + edge[1]) / 2.0, count);
+            }
+        }
+        histogram
+    }
+
+    /// from_bins creates a histogram from `(value, count)` pairs as returned by
+    /// [`Histogram::iter_bins`], for example to restore bins stored elsewhere. The pairs may
+    /// come in any order: they are put in order with [`Histogram::repair`], which drops NaN
+    /// values and empty pairs, combines equal values and merges the bins down to `max_bins`,
+    /// and the extremes are the first and last value. Like [`Histogram::new`], `max_bins` is
+    /// at least 10.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let histogram = Histogram::from_bins(&[(3.0, 1), (1.0, 2), (3.0, 1)], 10);
+    ///  let bins: Vec<(f64, usize)> = histogram.iter_bins().collect();
+    ///  assert_eq!(bins, vec![(1.0, 2), (3.0, 2)]);
+    ///  assert_eq!(histogram.mean(), Some(2.0));
+    /// ```
+    pub fn from_bins(bins: &[(f64, usize)], max_bins: usize) -> Histogram {
+        let mut histogram = Histogram::new(max_bins);
+        histogram.bins = bins
+            .iter()
+            .map(|(value, count)| Bin::new(*value, *count))
+            .collect();
+        histogram.repair();
+        histogram
+    }
+
+    /// repair restores the invariants of the bins, a safety net for bulk operations which
+    /// may break them: bins with a NaN value are dropped, the bins are sorted by value, bins
+    /// of exactly the same value are combined, the total, the extremes, the mean and the
+    /// variance are recomputed from the bins, and the bins are merged down to `max_bins`.
+    /// The extremes can only be as precise as the bins. A histogram whose invariants hold is
+    /// left as it is.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(3.0);
+    ///  histogram.add(1.0);
+    ///  histogram.repair();
+    ///  let bins: Vec<(f64, usize)> = histogram.iter_bins().collect();
+    ///  assert_eq!(bins, vec![(1.0, 1), (3.0, 1)]);
+    ///  assert_eq!(histogram.min(), Some(1.0));
+    /// ```
+    pub fn repair(&mut self) {
+        let mut bins: Vec<Bin> = std::mem::replace(&mut self.bins, LinkedList::new())
+            .into_iter()
+            .filter(|i| !i.value.is_nan() && (i.count > 0 || self.anchored))
+            .collect();
+        bins.sort_by(|a, b| a.value.total_cmp(&b.value));
+        let hook = self.saturation_hook;
+        let mut sorted: Vec<Bin> = Vec::with_capacity(bins.len());
+        for bin in bins {
+            push_sorted(&mut sorted, bin, hook);
+        }
+        self.bins = sorted.into_iter().collect();
+        self.total = self.bins.iter().map(|i| i.count as u64).sum();
+        self.refresh_extremes(true, true);
+        self.refresh_moments();
+        self.merge_down_to(self.max_bins);
+        debug_assert!(self.is_sorted());
+    }
+
+    /// resample redistributes the counts onto `target_max_bins` evenly spaced bins between the
+    /// minimum and maximum value, the count of each cell follows the linear interpolation of
+    /// the cumulative counts (see [`Histogram::ratio_below`]). This re-grids histograms of
+    /// different resolution onto a common grid for comparison, and can give a coarse
+    /// histogram more bins. The counts are rounded so the total is kept exactly, empty cells
+    /// are left out and the minimum and maximum stay the same. Like [`Histogram::new`],
+    /// `target_max_bins` is at least 10.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
     ///       histogram.add(i as f64);
     ///  }
+    ///  let resampled = histogram.resample(50);
+    ///  assert_eq!(resampled.iter_bins().count(), 50);
+    ///  assert_eq!(resampled.report().unwrap().total, 100);
     /// ```
-    pub fn add(&mut self, number: f64) {
-        self.total = self.total + 1;
-        match self.min {
-            Some(v) => {
-                if number < v {
-                    self.min = Some(number)
-                }
-            }
-            _ => {
-                self.min = Some(number);
-            }
+    pub fn resample(&self, target_max_bins: usize) -> Histogram {
+        let mut histogram = Histogram::new(target_max_bins);
+        let (min, max) = match (self.min, self.max) {
+            (Some(min), Some(max)) if self.total > 0 => (min, max),
+            _ => return histogram,
+        };
+        let cells = histogram.max_bins;
+        let width = (max - min) / cells as f64;
+        let mut counts = Vec::with_capacity(cells);
+        let mut remainders = Vec::with_capacity(cells);
+        let mut assigned: u64 = 0;
+        let mut below = 0.0;
+        for index in 0..cells {
+            let upper = if index + 1 == cells {
+                1.0
+            } else {
+                self.ratio_below(min + width * (index + 1) as f64)
+                    .unwrap_or(1.0)
+            };
+            let exact = (upper - below) * self.total as f64;
+            below = upper;
+            counts.push(exact.floor() as u64);
+            assigned += exact.floor() as u64;
+            remainders.push((exact - exact.floor(), index));
         }
-        match self.max {
-            Some(v) => {
-                if number > v {
-                    self.max = Some(number)
-                }
-            }
-            _ => {
-                self.max = Some(number);
-            }
+        // give the rounding leftover to the cells with the largest remainders
+        remainders.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        for (_, index) in remainders
+            .iter()
+            .take(self.total.saturating_sub(assigned) as usize)
+        {
+            counts[*index] += 1;
         }
-        // println!(" {}{:?}{:?}", self.total, self.max, self.min);
-        let mut cursor = self.bins.cursor();
-        loop {
-            match cursor.next() {
-                Some(v) => {
-                    if v.value == number {
-                        v.count += 1;
-                        return;
-                    }
-                    if v.value > number {
-                        let new_bin = Bin {
-                            value: number,
-                            count: 1,
-                        };
-                        cursor.seek_backward(1);
-                        cursor.insert(new_bin);
-                        self.merge_bin();
-                        return;
-                    }
-                }
-                None => break,
+        for (index, count) in counts.into_iter().enumerate() {
+            if count > 0 {
+                let value = if width > 0.0 {
+                    min + width * (index as f64 + 0.5)
+                } else {
+                    min
+                };
+                histogram.push_bin(Bin::new(value, count as usize));
             }
         }
-        let new_bin = Bin {
-            value: number,
-            count: 1,
-        };
-        cursor.seek_backward(1);
-        cursor.insert(new_bin);
-        // do the merge job after finish the insert
-        self.merge_bin();
+        histogram.min = self.min;
+        histogram.max = self.max;
+        histogram.merges = self.merges;
+        histogram.lossy = self.lossy;
+        histogram
     }
 
-    /// q-quantiles are values that partition a finite set of values into q subsets of
-    /// (nearly) equal sizes. for more detail please check
-    /// [wikipedia](https://en.wikipedia.org/wiki/Quantile)
-    #[allow(dead_code)]
-    pub fn quantile(&self, q: f64) -> Option<f64> {
-        let mut count = q * self.total as f64;
-        for i in self.bins.iter() {
-            count -= i.count as f64;
-            // println!("{} {} {:?}", q, i.count, i.value);
-            if count <= 0.0 {
-                return Some(i.value);
-            }
+    /// new_with_range creates a histogram whose `max_bins` bins are pre-seeded as evenly spaced
+    /// zero-count anchors over the expected value range `min..=max`. The anchors count toward
+    /// no statistic, they only hold bin positions across the range, so bin listings such as
+    /// [`Histogram::iter_bins`] or [`Histogram::sparkline`] span the whole range from the
+    /// first sample on. A value equal to an anchor lands in it, and when the bins overflow
+    /// the empty anchors closest to the real values are dropped before any real bins are
+    /// merged, so the quantiles of the first values stay exact just like with
+    /// [`Histogram::new`]. The anchors don't make the early quantiles any more accurate:
+    /// below `max_bins` distinct values both histograms hold the exact values, and above it
+    /// the anchors are gone. The anchors are lost on decay or rescaling.
+    /// # Panics
+    ///
+    /// Panics when `min` and `max` are not finite or `min` is not less than `max`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new_with_range(11, 0.0, 100.0);
+    ///  assert_eq!(histogram.mean(), None);
+    ///  histogram.add(30.0);
+    ///  assert_eq!(histogram.quantile(0.0), Some(30.0));
+    /// ```
+    pub fn new_with_range(max_bins: usize, min: f64, max: f64) -> Histogram {
+        assert!(
+            min.is_finite() && max.is_finite() && min < max,
+            "invalid range {}..={}",
+            min,
+            max
+        );
+        let mut histogram = Histogram::new(max_bins);
+        let steps = (histogram.max_bins - 1) as f64;
+        for i in 0..histogram.max_bins {
+            histogram
+                .bins
+                .push_back(Bin::new(min + (max - min) * i as f64 / steps, 0));
         }
-        None
+        histogram.anchored = true;
+        histogram
     }
-    /// Cumulative distribution function(aka: cdf) returns the value of the cumulative
-    /// distribution at value x. for more detail, please check [wikipedia](https://en.wikipedia.org/wiki/Cumulative_distribution_function)
-    #[allow(dead_code)]
-    pub fn cdf(&mut self, x: f64) -> Option<f64> {
-        let mut count = 0;
-        for i in self.bins.iter() {
-            if i.value <= x {
-                count += i.count
-            }
-        }
-        if self.total == 0 {
-            None
-        } else {
-            Some(count as f64 / self.total as f64)
-        }
+
+    /// builder returns a [`HistogramBuilder`] for creating a histogram with optional settings.
+    pub fn builder() -> HistogramBuilder {
+        HistogramBuilder::default()
     }
 
-    pub fn mean(&self) -> Option<f64> {
-        if self.total == 0 {
-            return None;
+    /// suggest_max_bins gives a rough recommendation of the bucket number for a desired
+    /// resolution. The heuristic assumes values spread over `data_range` and each bin
+    /// covers about `data_range / max_bins`, a quantile falls inside one bin so its error is
+    /// at most about half of the bin width, which gives `data_range / (2 * target_quantile_error)`.
+    /// The result is kept between 10 (the minimum bucket number) and 100,000, invalid inputs
+    /// return 100 which is the default bucket number.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  // latency between 0ms and 1000ms, quantiles within 5ms
+    ///  let max_bins = Histogram::suggest_max_bins(5.0, 1000.0);
+    ///  assert_eq!(max_bins, 100);
+    ///  let histogram = Histogram::new(max_bins);
+    /// ```
+    pub fn suggest_max_bins(target_quantile_error: f64, data_range: f64) -> usize {
+        if !(target_quantile_error > 0.0 && data_range > 0.0) {
+            return 100;
         }
-        let mut sum = 0.0;
-        for i in self.bins.iter() {
-            sum += i.value * i.count as f64
+        let bins = (data_range / (2.0 * target_quantile_error)).ceil();
+        if bins.is_nan() {
+            return 100;
         }
-        Some(sum / self.total as f64)
+        bins.clamp(10.0, 100_000.0) as usize
     }
-    #[allow(dead_code)]
-    pub fn variance(&self) -> Option<f64> {
-        if self.total == 0 {
+
+    // empty_like create an empty histogram which share the same configuration
+    // as current histogram.
+    fn empty_like(&self) -> Histogram {
+        let mut histogram = Histogram::try_new(self.max_bins).expect("max_bins is valid");
+        histogram.bounds = self.bounds;
+        histogram.saturation_hook = self.saturation_hook;
+        histogram.ema_decay = self.ema_decay;
+        histogram.tail_bins = self.tail_bins;
+        histogram.max_total = self.max_total;
+        histogram.top_k = self.top_k.as_ref().map(TopK::empty_like);
+        histogram.quantize = self.quantize;
+        histogram.reservoir = self.reservoir.as_ref().map(Reservoir::empty_like);
+        histogram.merge_slack = self.merge_slack;
+        histogram.snap_tolerance = self.snap_tolerance;
+        histogram.singleton_tolerance = self.singleton_tolerance;
+        histogram.track_order = self.track_order;
+        histogram.nan_policy = self.nan_policy;
+        histogram.tracker = self.tracker.as_ref().map(|_| Tracker::default());
+        histogram
+    }
+
+    /// return how many values have been clamped into the bounds
+    /// since the histogram created.
+    pub fn clamped_count(&self) -> u64 {
+        self.clamped
+    }
+
+    /// min returns the minimum value added into the histogram
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    /// max returns the maximum value added into the histogram
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    /// remove_bin deletes the bin at the sorted position `index` and returns its value and count,
+    /// or None if the index is out of range. When the first or last bin is removed the
+    /// minimum or maximum value is recomputed from the remaining bins, the raw extremes
+    /// are lost so the new value is the value of the new first or last bin.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=5 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.remove_bin(4), Some((5.0, 1)));
+    ///  assert_eq!(histogram.max(), Some(4.0));
+    /// ```
+    pub fn remove_bin(&mut self, index: usize) -> Option<(f64, usize)> {
+        if index >= self.bins.len() {
             return None;
         }
-        let mut sum = 0.0;
-        let mean = self.mean()?;
+        let bin = self.bins.remove(index)?;
+        self.total = self.total.saturating_sub(bin.count as u64);
+        self.refresh_extremes(index == 0, index == self.bins.len());
+        self.refresh_moments();
+        Some((bin.value, bin.count))
+    }
 
-        for i in self.bins.iter() {
-            sum += i.count as f64 * (i.value - mean) * (i.value - mean);
+    /// downsample scales every bin count proportionally so that the total becomes `target_total`,
+    /// the shape of the distribution is kept while the weight of the histogram shrinks, which
+    /// is useful before combining many histograms. Counts are rounded with the largest remainder
+    /// method so they sum exactly to the target, bins whose count rounds to zero are removed.
+    /// Nothing happens when `target_total` is not less than the current total.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=10000 {
+    ///       histogram.add((i % 100) as f64);
+    ///  }
+    ///  histogram.downsample(1000);
+    ///  assert_eq!(histogram.report().unwrap().total, 1000);
+    /// ```
+    pub fn downsample(&mut self, target_total: u64) {
+        if target_total < self.total {
+            self.rescale_to(target_total);
         }
-        Some(sum / self.total as f64)
     }
 
-    fn merge_bin(&mut self) {
-        if self.bins.len() <= self.max_bins {
-            return;
+    /// decay multiplies every bin count by `factor`, so that older values weigh less than the
+    /// values added afterwards. Counts are rounded like in [`Histogram::downsample`] and bins
+    /// whose count rounds to zero are removed. Nothing happens unless `factor` is inside `0.0..1.0`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  histogram.add_weighted(1.0, 100);
+    ///  histogram.decay(0.1);
+    ///  histogram.add_weighted(2.0, 10);
+    ///  assert_eq!(histogram.mean(), Some(1.5));
+    /// ```
+    pub fn decay(&mut self, factor: f64) {
+        if (0.0..1.0).contains(&factor) {
+            self.rescale_to((self.total as f64 * factor).round() as u64);
         }
-        let mut min_delta: f64 = 1e99;
-        let mut min_delta_index = 0;
-        let mut index = 0;
-        let mut last_bin_value: f64 = 0.0;
-        for i in self.bins.iter() {
-            if index == 0 {
-                last_bin_value = i.value;
-                index += 1;
-                continue;
-            }
+    }
 
-            let delta = i.value - last_bin_value;
-            if delta < min_delta {
-                min_delta = delta;
-                min_delta_index = index;
-            }
-            index += 1;
-            last_bin_value = i.value;
+    // age applies the exponential moving decay before a new value is recorded,
+    // the factors are accumulated until they halve the recorded weight.
+    fn age(&mut self, alpha: f64) {
+        self.pending_decay *= 1.0 - alpha;
+        if self.pending_decay < 0.5 {
+            let factor = self.pending_decay;
+            self.pending_decay = 1.0;
+            self.decay(factor);
         }
-        // must have a current and last bin
-        let current_bin = self
-            .bins
-            .remove(min_delta_index)
-            .expect("retrieve current bin fail");
-        let last_bin = self
-            .bins
-            .remove(min_delta_index - 1)
-            .expect("retrieve last bin fail");
-
-        let total_count = current_bin.count + last_bin.count;
-        let value = (current_bin.value * current_bin.count as f64
-            + last_bin.value * last_bin.count as f64)
-            / total_count as f64;
-        let merged_bin = Bin {
-            value,
-            count: total_count,
-        };
-        self.bins.insert(min_delta_index - 1, merged_bin);
     }
-    #[allow(dead_code)]
-    /// to_string method print the result in a simple visulazation style,
-    /// each line print the bucket and the size in a dot mode.
+
+    /// clamp_total caps the total count at `max_total` for long running streams: whenever the
+    /// total exceeds the cap all counts are scaled down proportionally to 95% of the cap,
+    /// like [`Histogram::downsample`], so the recent values weigh more than the old ones and
+    /// the total stays just under the cap. The 5% headroom keeps the O(n) scaling to once per
+    /// twentieth of the cap added, and the rounding of the counts from piling up. A cap of 0
+    /// removes the limit.
     /// # Examples
     ///
     /// ```
-    ///  use rand::distributions::{Normal, Distribution};
     ///  use crate::stream_histogram::Histogram;
-    ///  let normal = Normal::new(10.0, 10.0);
     ///  let mut histogram = Histogram::new(20);
-    ///  for _i in 1..=100000 {
-    ///       let v = normal.sample(&mut rand::thread_rng());
-    ///       histogram.add(v as f64);
+    ///  histogram.clamp_total(1000);
+    ///  for i in 1..=10000 {
+    ///       histogram.add((i % 100) as f64);
     ///  }
-    ///  println!("{}", histogram.to_string());
+    ///  let total = histogram.report().unwrap().total;
+    ///  assert!(950 <= total && total <= 1000);
     /// ```
-    pub fn to_string(&self) -> String {
-        let mut result = format!("Total: {}\n", self.total);
-        for i in self.bins.iter() {
-            let mut bar = format!("{}", i.value);
-            let size = (i.count as f64 / self.total as f64 * 100.0) as usize;
-            for _i in 1..size {
-                bar += &*String::from(".");
+    pub fn clamp_total(&mut self, max_total: u64) {
+        self.max_total = Some(max_total).filter(|&max| max > 0);
+        self.enforce_max_total();
+    }
+
+    // enforce_max_total scales the total to 95% of the cap set by clamp_total once it
+    // exceeds the cap.
+    fn enforce_max_total(&mut self) {
+        if let Some(max_total) = self.max_total {
+            if self.total > max_total {
+                self.rescale_to(max_total - max_total / 20);
             }
-            result += &*(bar.add("\n"));
         }
-        result
     }
 
-    /// return a report from current histogram
-    /// # Example
-    /// ```
-    /// use crate::stream_histogram::Histogram;
-    /// let mut histogram = Histogram::new(10);
-    /// for i in 1..=100 {
-    ///     histogram.add(i as f64);
-    /// }
-    /// if let Some(report) = histogram.report(){
-    ///    println!("{:?}", report);
-    /// }
-    /// ```
-    pub fn report(&self) -> Option<HistogramReport> {
-        HistogramReport::new(&self)
+    // rescale_to scales the bin counts so that they sum to `target_total`, using the
+    // largest remainder method for rounding and dropping the bins left empty.
+    fn rescale_to(&mut self, target_total: u64) {
+        if self.total == 0 {
+            return;
+        }
+        self.lossy = true;
+        let factor = target_total as f64 / self.total as f64;
+        let mut bins: Vec<Bin> = std::mem::replace(&mut self.bins, LinkedList::new())
+            .into_iter()
+            .collect();
+        let mut remainders = Vec::with_capacity(bins.len());
+        let mut assigned: u64 = 0;
+        for (index, i) in bins.iter_mut().enumerate() {
+            let exact = i.count as f64 * factor;
+            i.count = exact.floor() as usize;
+            i.m2 *= factor;
+            assigned += i.count as u64;
+            remainders.push((exact - exact.floor(), index));
+        }
+        // give the rounding leftover to the bins with the largest remainders
+        remainders.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        for (_, index) in remainders
+            .iter()
+            .take(target_total.saturating_sub(assigned) as usize)
+        {
+            bins[*index].count += 1;
+        }
+        let lower = bins.first().is_some_and(|i| i.count == 0);
+        let upper = bins.last().is_some_and(|i| i.count == 0);
+        self.bins = bins.into_iter().filter(|i| i.count > 0).collect();
+        self.total = self.bins.iter().map(|i| i.count as u64).sum();
+        self.refresh_extremes(lower, upper);
+        self.refresh_moments();
     }
-}
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub struct HistogramReport {
-    pub total: u64,
-    pub mean: f64,
-    pub max: f64,
-    pub min: f64,
-    pub percent99: f64,
-    pub percent90: f64,
-    pub percent50: f64,
-}
 
-#[allow(dead_code)]
-impl HistogramReport {
-    pub fn new(histogram: &Histogram) -> Option<Self> {
-        Some(HistogramReport {
-            total: histogram.total,
-            mean: histogram.mean()?,
-            max: histogram.max?,
-            min: histogram.min?,
-            percent99: histogram.quantile(0.99)?,
-            percent90: histogram.quantile(0.90)?,
-            percent50: histogram.quantile(0.50)?,
-        })
+    // refresh_extremes recompute the minimum and maximum value from the bins
+    // after the first or last bin has been removed.
+    fn refresh_extremes(&mut self, lower: bool, upper: bool) {
+        if self.bins.is_empty() {
+            self.min = None;
+            self.max = None;
+            return;
+        }
+        if lower {
+            self.min = self.bins.front().map(|i| i.value);
+        }
+        if upper {
+            self.max = self.bins.back().map(|i| i.value);
+        }
     }
-}
 
-#[cfg(test)]
+    /// is_exact returns true while no bins have been merged, every bin then holds a single
+    /// distinct value and quantiles, mean and variance are exact. It becomes false once the
+    /// number of distinct values exceeds `max_bins` and the histogram starts approximating,
+    /// once `quantize` moved a value to its step, or once the counts were rescaled by
+    /// [`Histogram::downsample`], [`Histogram::decay`], `ema_decay` or [`Histogram::clamp_total`].
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=10 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert!(histogram.is_exact());
+    ///  histogram.add(11.0);
+    ///  assert!(!histogram.is_exact());
+    /// ```
+    pub fn is_exact(&self) -> bool {
+        self.merges == 0 && !self.lossy
+    }
+
+    /// merge_count returns how many times two bins have been combined into one.
+    /// Each merge loses some information, so a value which is high relative to
+    /// the total number of values suggests `max_bins` is under-provisioned.
+    pub fn merge_count(&self) -> u64 {
+        self.merges
+    }
+
+    /// add method update histogram data structure from stream
+    /// each time update a new value current value must be f64
+    /// after update, the inner process will check the bucket number
+    /// and merge two smallest gap bucket into one bucket.
+    /// NaN can't be ordered among the bins and is ignored, see [`NanPolicy`] for counting
+    /// or detecting it.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100000 {
+    ///       histogram.add(i as f64);
+    ///  }
+    /// ```
+    pub fn add(&mut self, number: f64) {
+        self.add_weighted(number, 1);
+    }
+
+    /// add_weighted add a value which has been observed `count` times, it is the same
+    /// as calling [`Histogram::add`] `count` times but only search the bins once.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  histogram.add_weighted(1.0, 3);
+    ///  histogram.add(2.0);
+    ///  assert_eq!(histogram.mean(), Some(1.25));
+    /// ```
+    pub fn add_weighted(&mut self, number: f64, count: usize) {
+        self.record(number, count);
+    }
+
+    /// add_indexed adds a value like [`Histogram::add`] and returns the index of the bin,
+    /// in ascending order of value, which the value was inserted into or added to. The index
+    /// is taken before the bins are merged, so the merge which follows when the bins overflow
+    /// may move the value into a neighbouring bin or shift the index by one. Returns None
+    /// when the value is an ignored NaN.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  assert_eq!(histogram.add_indexed(2.0), Some(0));
+    ///  assert_eq!(histogram.add_indexed(1.0), Some(0));
+    ///  assert_eq!(histogram.add_indexed(2.0), Some(1));
+    ///  assert_eq!(histogram.add_indexed(f64::NAN), None);
+    /// ```
+    pub fn add_indexed(&mut self, number: f64) -> Option<usize> {
+        self.record(number, 1)
+    }
+
+    /// add_and_flag adds a value like [`Histogram::add`] and returns whether it exceeds the
+    /// `upper_q` quantile of the values recorded before it, for inline anomaly alerts without
+    /// a separate query. The first value, and NaN which is ignored, are never flagged.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert!(!histogram.add_and_flag(50.0, 0.99));
+    ///  assert!(histogram.add_and_flag(500.0, 0.99));
+    /// ```
+    pub fn add_and_flag(&mut self, number: f64, upper_q: f64) -> bool {
+        let flagged = self.quantile(upper_q).is_some_and(|limit| number > limit);
+        self.add(number);
+        flagged
+    }
+
+    // record adds the value and returns the index of its bin before merging, None when
+    // nothing was added.
+    fn record(&mut self, number: f64, count: usize) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+        if number.is_nan() {
+            self.reject_nan(count as u64);
+            return None;
+        }
+        let before = self.tracker_key();
+        if let Some(alpha) = self.ema_decay {
+            self.age(alpha);
+        }
+        self.update_insertion_order(number);
+        let quantized = self.quantized(number);
+        self.lossy |= quantized != number;
+        let number = self.clamp(quantized, count);
+        if let Some(top_k) = self.top_k.as_mut() {
+            top_k.add(number, count);
+        }
+        if let Some(reservoir) = self.reservoir.as_mut() {
+            reservoir.add(number, count);
+        }
+        self.total = self.total.saturating_add(count as u64);
+        self.moments.add(count as f64, number, 0.0);
+        self.update_extremes(number);
+        let bin = Bin::new(number, count);
+        let index = match self.snap_tolerance {
+            Some(tolerance) => self.snap_bin(bin, tolerance),
+            None => self.insert_bin(bin),
+        };
+        self.enforce_max_total();
+        debug_assert!(self.is_sorted());
+        self.update_tracker(before, number, count);
+        Some(index)
+    }
+
+    // tracker_key identifies the state the percentile tracker was computed for.
+    fn tracker_key(&self) -> (u64, u64, usize) {
+        (self.total, self.merges, self.bins.len())
+    }
+
+    // update_tracker refreshes the tracked percentiles after `count` values equal to
+    // `number` were recorded, in O(1) when no bin moved and the percentiles stayed in their
+    // bins, otherwise with one walk over the bins.
+    fn update_tracker(&mut self, before: (u64, u64, usize), number: f64, count: usize) {
+        let key = self.tracker_key();
+        let mut tracker = match self.tracker.take() {
+            Some(tracker) => tracker,
+            None => return,
+        };
+        let added = before.0.checked_add(count as u64) == Some(key.0)
+            && before.1 == key.1
+            && key.2 <= before.2 + 1
+            && self.snap_tolerance.is_none();
+        if added && tracker.is_fresh(before) {
+            tracker.add(number, count, self.bins.iter(), key);
+        } else {
+            tracker.refresh(self.bins.iter(), key);
+        }
+        self.tracker = Some(tracker);
+    }
+
+    /// tracked_percentiles returns the p50, p90 and p99 quantiles, the same values as
+    /// [`Histogram::quantile`]. With [`HistogramBuilder::track_percentiles`] they are kept up
+    /// to date on every add: as long as no bins are merged (before the histogram is full, or
+    /// between the batches of [`HistogramBuilder::merge_slack`]) an add updates them in O(1)
+    /// unless a percentile moves to another bin, otherwise and after merges the bins are walked
+    /// once for all three. Without tracking, or after other changes such as
+    /// [`Histogram::merge`], they are computed with one walk. NaN when empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::builder().track_percentiles(true).build();
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.tracked_percentiles(), [50.0, 90.0, 99.0]);
+    /// ```
+    pub fn tracked_percentiles(&self) -> [f64; 3] {
+        let key = self.tracker_key();
+        match self.tracker.as_ref() {
+            Some(tracker) if tracker.is_fresh(key) => tracker.values(),
+            _ => {
+                let mut tracker = Tracker::default();
+                tracker.refresh(self.bins.iter(), key);
+                tracker.values()
+            }
+        }
+    }
+
+    // snap_bin combines the bin with the nearest bin within `tolerance`, see
+    // HistogramBuilder::nearest_bin, or inserts it when there is none.
+    fn snap_bin(&mut self, bin: Bin, tolerance: f64) -> usize {
+        let mut nearest: Option<(f64, usize)> = None;
+        for (index, i) in self.bins.iter().enumerate() {
+            let distance = (i.value - bin.value).abs();
+            if distance <= tolerance && nearest.is_none_or(|(d, _)| distance < d) {
+                nearest = Some((distance, index));
+            }
+            if i.value > bin.value + tolerance {
+                break;
+            }
+        }
+        match nearest {
+            Some((distance, index)) if distance > 0.0 => {
+                let current = self.bins.remove(index).expect("retrieve nearest bin fail");
+                let combined = if current.value < bin.value {
+                    combine_bins(current, bin, self.saturation_hook)
+                } else {
+                    combine_bins(bin, current, self.saturation_hook)
+                };
+                self.bins.insert(index, combined);
+                self.merges += 1;
+                index
+            }
+            _ => self.insert_bin(bin),
+        }
+    }
+
+    /// try_add is the checked variant of [`Histogram::add`], infinite values are rejected with
+    /// [`HistogramError::InvalidValue`], and [`HistogramError::Full`] is returned once the
+    /// total count can't grow any further. NaN is ignored like by [`Histogram::add`] and Ok
+    /// is returned, unless the histogram is built with [`NanPolicy::TreatAsError`], which
+    /// rejects it with [`HistogramError::InvalidValue`].
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::{Histogram, HistogramError};
+    ///  let mut histogram = Histogram::new(20);
+    ///  assert!(histogram.try_add(1.0).is_ok());
+    ///  assert!(histogram.try_add(f64::NAN).is_ok());
+    ///  assert!(matches!(histogram.try_add(f64::INFINITY), Err(HistogramError::InvalidValue(_))));
+    /// ```
+    pub fn try_add(&mut self, number: f64) -> Result<(), HistogramError> {
+        self.try_add_weighted(number, 1)
+    }
+
+    /// try_add_weighted is the checked variant of [`Histogram::add_weighted`], see
+    /// [`Histogram::try_add`].
+    pub fn try_add_weighted(&mut self, number: f64, count: usize) -> Result<(), HistogramError> {
+        if number.is_nan() && self.nan_policy != NanPolicy::TreatAsError {
+            self.reject_nan(count as u64);
+            return Ok(());
+        }
+        if !number.is_finite() {
+            return Err(HistogramError::InvalidValue(number));
+        }
+        if self.total == u64::MAX {
+            return Err(HistogramError::Full);
+        }
+        self.add_weighted(number, count);
+        Ok(())
+    }
+
+    fn reject_nan(&mut self, count: u64) {
+        if self.nan_policy == NanPolicy::Skip {
+            self.nan_count = self.nan_count.saturating_add(count);
+        }
+    }
+
+    /// nan_count returns how many NaN values have been ignored since the histogram was
+    /// created, only counted under [`NanPolicy::Skip`].
+    pub fn nan_count(&self) -> u64 {
+        self.nan_count
+    }
+
+    /// buffered returns a [`BufferedHistogram`] to add values at very high ingest rates.
+    /// Values are staged in an unsorted buffer and merged into the bins once the buffer holds
+    /// `max_bins` values, which avoids walking the bins for every value. The cost per value
+    /// stays nearly flat as `max_bins` grows, while `add` grows linearly, in the bundled
+    /// benchmark on random input `add` is faster below about 80 bins and the buffered path
+    /// wins above. The rest of the buffer is flushed when the BufferedHistogram is dropped,
+    /// and the histogram can't be queried before, so queries always see every value.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  let mut buffered = histogram.buffered();
+    ///  for i in 1..=1005 {
+    ///       buffered.add(i as f64);
+    ///  }
+    ///  drop(buffered);
+    ///  assert_eq!(histogram.report().unwrap().total, 1005);
+    /// ```
+    pub fn buffered(&mut self) -> BufferedHistogram<'_> {
+        let buffer = Vec::with_capacity(self.max_bins);
+        BufferedHistogram {
+            histogram: self,
+            buffer,
+        }
+    }
+
+    /// add_slice adds all `values` at once: the slice is sorted and merged into the bins in a
+    /// single pass, and the bins are compacted once at the end. Like
+    /// [`Histogram::buffered`] it pays off for larger `max_bins`: in the bundled benchmark
+    /// it is faster than calling [`Histogram::add`] for each value at 100 bins, but slower at
+    /// 20, where merging the whole slice down at once costs more. It is the merge
+    /// [`BufferedHistogram::flush`] does for the buffered values, without the staging
+    /// buffer. NaN values are skipped.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  let values: Vec<f64> = (1..=1000).map(|i| i as f64).collect();
+    ///  histogram.add_slice(&values);
+    ///  assert_eq!(histogram.report().unwrap().total, 1000);
+    /// ```
+    pub fn add_slice(&mut self, values: &[f64]) {
+        if values.is_empty() {
+            return;
+        }
+        self.merge_values(values.to_vec());
+    }
+
+    // merge_values sorts the values and merges them into the bins in one pass.
+    fn merge_values(&mut self, mut values: Vec<f64>) {
+        let len = values.len();
+        values.retain(|v| !v.is_nan());
+        self.reject_nan((len - values.len()) as u64);
+        for v in values.iter_mut() {
+            self.update_insertion_order(*v);
+            let quantized = self.quantized(*v);
+            self.lossy |= quantized != *v;
+            *v = self.clamp(quantized, 1);
+            self.moments.add(1.0, *v, 0.0);
+            self.update_extremes(*v);
+            if let Some(top_k) = self.top_k.as_mut() {
+                top_k.add(*v, 1);
+            }
+            if let Some(reservoir) = self.reservoir.as_mut() {
+                reservoir.add(*v, 1);
+            }
+        }
+        self.total = self.total.saturating_add(values.len() as u64);
+        values.sort_by(|a, b| a.total_cmp(b));
+        let hook = self.saturation_hook;
+        let mut bins: Vec<Bin> = Vec::with_capacity(self.bins.len() + values.len());
+        let mut values = values.into_iter().peekable();
+        for bin in std::mem::replace(&mut self.bins, LinkedList::new()) {
+            while let Some(v) = values.next_if(|v| *v < bin.value) {
+                push_sorted(&mut bins, Bin::new(v, 1), hook);
+            }
+            push_sorted(&mut bins, bin, hook);
+        }
+        for v in values {
+            push_sorted(&mut bins, Bin::new(v, 1), hook);
+        }
+        self.bins = bins.into_iter().collect();
+        self.merge_bin();
+        self.enforce_max_total();
+        debug_assert!(self.is_sorted());
+    }
+
+    /// add_duration records that the system stayed at `number` for `seconds`, so the
+    /// histogram weights values by time rather than by events (e.g. queue depth over time)
+    /// and [`Histogram::mean`] becomes a time-weighted average. Counts are integers, so the
+    /// duration is recorded in whole milliseconds: each millisecond adds one to the count and
+    /// the total, durations shorter than half a millisecond are ignored.
+    ///
+    /// # Mixing with add
+    ///
+    /// Do not mix `add_duration` with [`Histogram::add`] or any other event based add on
+    /// the same histogram. The time weight is stored in the counts and the total, so one
+    /// `add` counts as a single millisecond: the mean, the quantiles and the total
+    /// silently turn into a blend of events and milliseconds. Keep time-in-state values in
+    /// their own histogram.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  histogram.add_duration(10.0, 3.0);
+    ///  histogram.add_duration(20.0, 1.0);
+    ///  assert_eq!(histogram.mean(), Some(12.5));
+    /// ```
+    pub fn add_duration(&mut self, number: f64, seconds: f64) {
+        const TICKS_PER_SECOND: f64 = 1000.0;
+        if seconds.is_nan() || seconds <= 0.0 {
+            return;
+        }
+        let ticks = (seconds * TICKS_PER_SECOND).round();
+        self.add_weighted(number, ticks.min(usize::MAX as f64) as usize);
+    }
+
+    /// on_saturation registers a callback which is invoked with the bin value when the
+    /// count of a bin reaches `usize::MAX`. Counts saturate instead of wrapping around,
+    /// so the statistics stay meaningful but are no longer exact after a saturation.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  histogram.on_saturation(|value| eprintln!("bin {} saturated", value));
+    ///  histogram.add_weighted(1.0, usize::MAX);
+    ///  histogram.add(1.0);
+    /// ```
+    pub fn on_saturation(&mut self, hook: fn(f64)) {
+        self.saturation_hook = Some(hook);
+    }
+
+    /// add_timed add a value like [`Histogram::add`] and also record its arrival time,
+    /// `timestamp` is in seconds (for example the unix time). The value distribution is
+    /// not affected by the timestamps, they are only used by [`Histogram::rate`]. A NaN
+    /// ignored by `add` is not timed either.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 0..=100 {
+    ///       histogram.add_timed(i as f64, i as f64 * 0.5);
+    ///  }
+    ///  assert_eq!(histogram.rate(), Some(2.0));
+    /// ```
+    pub fn add_timed(&mut self, number: f64, timestamp: f64) {
+        if self.record(number, 1).is_some() {
+            self.timed += 1;
+            self.update_timestamps(timestamp, timestamp);
+        }
+    }
+
+    fn update_timestamps(&mut self, first: f64, last: f64) {
+        self.timestamps = match self.timestamps {
+            Some((v1, v2)) => Some((first.min(v1), last.max(v2))),
+            None => Some((first, last)),
+        };
+    }
+
+    /// rate returns the number of observations per second between the first and the last
+    /// timestamp recorded by [`Histogram::add_timed`], None when less than two timed values
+    /// have been added or all of them share the same timestamp.
+    pub fn rate(&self) -> Option<f64> {
+        let (first, last) = self.timestamps?;
+        if self.timed < 2 || last <= first {
+            return None;
+        }
+        Some((self.timed - 1) as f64 / (last - first))
+    }
+
+    fn update_insertion_order(&mut self, number: f64) {
+        if self.track_order {
+            let first = self.insertion_order.map_or(number, |(first, _)| first);
+            self.insertion_order = Some((first, number));
+        }
+    }
+
+    /// first_value returns the first value added, before clamping or quantizing, as opposed to
+    /// [`Histogram::min`] which is the smallest one. It is only tracked when enabled with
+    /// [`HistogramBuilder::track_insertion_order`], otherwise and when nothing was added it
+    /// returns None. Buffered values count from the moment they are flushed, and a merged
+    /// histogram counts as added after the values already recorded.
+    pub fn first_value(&self) -> Option<f64> {
+        self.insertion_order.map(|(first, _)| first)
+    }
+
+    /// last_value returns the latest value added, see [`Histogram::first_value`].
+    pub fn last_value(&self) -> Option<f64> {
+        self.insertion_order.map(|(_, last)| last)
+    }
+
+    /// merge method folds all bins of another histogram into this one,
+    /// the bucket number of current histogram is kept, so bins will be merged
+    /// again until it fit into the `max_bins` of current histogram.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut first = Histogram::new(20);
+    ///  let mut second = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       first.add(i as f64);
+    ///       second.add((i + 100) as f64);
+    ///  }
+    ///  first.merge(&second);
+    ///  assert_eq!(first.report().unwrap().max, 200.0);
+    /// ```
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.total == 0 {
+            return;
+        }
+        self.anchored |= other.anchored;
+        self.nan_count = self.nan_count.saturating_add(other.nan_count);
+        self.total = self.total.saturating_add(other.total);
+        self.moments
+            .add(other.moments.count, other.moments.mean, other.moments.m2);
+        self.merges += other.merges;
+        self.lossy |= other.lossy;
+        if let (Some(top_k), Some(other)) = (self.top_k.as_mut(), other.top_k.as_ref()) {
+            top_k.merge(other);
+        }
+        if let (Some(reservoir), Some(other)) = (self.reservoir.as_mut(), other.reservoir.as_ref())
+        {
+            reservoir.merge(other);
+        }
+        self.timed += other.timed;
+        if let Some((first, last)) = other.timestamps {
+            self.update_timestamps(first, last);
+        }
+        if self.track_order {
+            if let Some((first, last)) = other.insertion_order {
+                let first = self.insertion_order.map_or(first, |(v, _)| v);
+                self.insertion_order = Some((first, last));
+            }
+        }
+        if let Some(v) = other.min {
+            self.update_extremes(v);
+        }
+        if let Some(v) = other.max {
+            self.update_extremes(v);
+        }
+        for i in other.bins.iter() {
+            self.insert_bin(i.clone());
+        }
+        self.enforce_max_total();
+        debug_assert!(self.is_sorted());
+    }
+
+    /// merge_range folds only the bins of `other` whose values lie inside `low..=high` into
+    /// this one, e.g. to leave out known garbage at the extremes when combining time slices.
+    /// The total and the extremes grow by the merged bins only, the extremes of `other` are
+    /// used when its outermost bins are merged. Empty anchors of `other` (see
+    /// [`Histogram::new_with_range`]) are skipped, and when any bin is merged this histogram
+    /// is no longer exact if `other` was not. Top values, the reservoir and the timestamps
+    /// of `other` are not merged.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut first = Histogram::new(20);
+    ///  let mut second = Histogram::new(20);
+    ///  for i in 1..=10 {
+    ///       first.add(i as f64);
+    ///       second.add(i as f64 * 100.0);
+    ///  }
+    ///  first.merge_range(&second, 0.0, 500.0);
+    ///  assert_eq!(first.report().unwrap().total, 15);
+    ///  assert_eq!(first.max(), Some(500.0));
+    /// ```
+    pub fn merge_range(&mut self, other: &Histogram, low: f64, high: f64) {
+        let in_range = |v: f64| low <= v && v <= high;
+        let counted = || other.bins.iter().enumerate().filter(|(_, i)| i.count > 0);
+        let first = counted().next().map(|(index, _)| index);
+        let last = counted().next_back().map(|(index, _)| index);
+        let mut merged = false;
+        for (index, i) in counted() {
+            if !in_range(i.value) {
+                continue;
+            }
+            merged = true;
+            let mut lowest = i.value;
+            let mut highest = i.value;
+            if Some(index) == first {
+                lowest = other.min.map_or(lowest, |v| v.max(low).min(lowest));
+            }
+            if Some(index) == last {
+                highest = other.max.map_or(highest, |v| v.min(high).max(highest));
+            }
+            self.update_extremes(lowest);
+            self.update_extremes(highest);
+            self.total = self.total.saturating_add(i.count as u64);
+            self.moments.add(i.count as f64, i.value, i.m2);
+            self.insert_bin(i.clone());
+        }
+        if merged {
+            self.merges += other.merges;
+            self.lossy |= other.lossy;
+        }
+        self.enforce_max_total();
+        debug_assert!(self.is_sorted());
+    }
+
+    /// split_at partition the histogram into two histograms at value `x`, the first one
+    /// holds the values less than or equal to `x` and the second one the values greater than `x`.
+    /// The bin which straddles `x` (see [`Histogram::weighted_quantile`] for the range a bin covers)
+    /// has its count divided proportionally between both sides. Both results inherit `max_bins`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let (fast, slow) = histogram.split_at(30.0);
+    ///  assert_eq!(fast.report().unwrap().total + slow.report().unwrap().total, 100);
+    /// ```
+    pub fn split_at(&self, x: f64) -> (Histogram, Histogram) {
+        let mut lower = self.empty_like();
+        let mut upper = self.empty_like();
+        for (i, (low, high)) in self.bins.iter().zip(self.bin_bounds()) {
+            if high <= x || (low >= high && i.value <= x) {
+                lower.push_bin(i.clone());
+            } else if low > x {
+                upper.push_bin(i.clone());
+            } else {
+                // the bin straddles x, divide its count proportionally
+                let fraction = (x - low) / (high - low);
+                let lower_count = (i.count as f64 * fraction).round() as usize;
+                let upper_count = i.count - lower_count;
+                if i.value <= x {
+                    lower.push_bin(i.part(i.value, lower_count));
+                    upper.push_bin(i.part((x + high) / 2.0, upper_count));
+                } else {
+                    lower.push_bin(i.part((low + x) / 2.0, lower_count));
+                    upper.push_bin(i.part(i.value, upper_count));
+                }
+            }
+        }
+        if let (Some(min), true) = (self.min, lower.total > 0) {
+            lower.update_extremes(min);
+        }
+        if let (Some(max), true) = (self.max, upper.total > 0) {
+            upper.update_extremes(max);
+        }
+        (lower, upper)
+    }
+
+    // is_sorted checks the invariant every operation keeps: the bin values ascend.
+    fn is_sorted(&self) -> bool {
+        let mut last = f64::NEG_INFINITY;
+        self.bins.iter().all(|i| {
+            let sorted = last <= i.value;
+            last = i.value;
+            sorted
+        })
+    }
+
+    fn push_bin(&mut self, bin: Bin) {
+        if bin.count == 0 {
+            return;
+        }
+        self.total = self.total.saturating_add(bin.count as u64);
+        self.moments.add(bin.count as f64, bin.value, bin.m2);
+        self.update_extremes(bin.value);
+        self.insert_bin(bin);
+    }
+
+    // quantized snaps the number to the nearest multiple of the quantize step.
+    fn quantized(&self, number: f64) -> f64 {
+        match self.quantize {
+            Some(step) => (number / step).round() * step,
+            None => number,
+        }
+    }
+
+    fn clamp(&mut self, number: f64, count: usize) -> f64 {
+        match self.bounds {
+            Some((lower, _)) if number < lower => {
+                self.clamped = self.clamped.saturating_add(count as u64);
+                lower
+            }
+            Some((_, upper)) if number > upper => {
+                self.clamped = self.clamped.saturating_add(count as u64);
+                upper
+            }
+            _ => number,
+        }
+    }
+
+    fn update_extremes(&mut self, number: f64) {
+        match self.min {
+            Some(v) => {
+                if number < v {
+                    self.min = Some(number)
+                }
+            }
+            _ => {
+                self.min = Some(number);
+            }
+        }
+        match self.max {
+            Some(v) => {
+                if number > v {
+                    self.max = Some(number)
+                }
+            }
+            _ => {
+                self.max = Some(number);
+            }
+        }
+    }
+
+    // insert_bin adds the bin at its sorted position and returns that index,
+    // taken before merging.
+    fn insert_bin(&mut self, bin: Bin) -> usize {
+        let hook = self.saturation_hook;
+        let mut cursor = self.bins.cursor();
+        let mut index = 0;
+        while let Some(v) = cursor.next() {
+            if v.value == bin.value {
+                v.count = saturating_count(v.count, bin.count, bin.value, hook);
+                v.m2 += bin.m2;
+                return index;
+            }
+            if v.value > bin.value {
+                cursor.seek_backward(1);
+                cursor.insert(bin);
+                self.merge_bin();
+                return index;
+            }
+            index += 1;
+        }
+        cursor.seek_backward(1);
+        cursor.insert(bin);
+        // do the merge job after finish the insert
+        self.merge_bin();
+        index
+    }
+
+    /// q-quantiles are values that partition a finite set of values into q subsets of
+    /// (nearly) equal sizes. for more detail please check
+    /// [wikipedia](https://en.wikipedia.org/wiki/Quantile)
+    ///
+    /// The result is the value of the first bin where the cumulative count reaches `q * total`,
+    /// so every quantile inside a plateau of repeated values returns that value, and a quantile
+    /// exactly at the end of a plateau returns the plateau rather than the next bin.
+    /// A `q` which overshoots 1 only by accumulated float error (for example a sum of
+    /// fractions) returns the largest bin value, larger `q` return None.
+    #[allow(dead_code)]
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let rank = target_rank(q, self.total)?;
+        if let Some(value) = self.quantile_for_count(rank) {
+            return Some(value);
+        }
+        // the threshold was never crossed, accept q slightly above 1 from float error
+        if q <= MAX_QUANTILE {
+            return self
+                .bins
+                .iter()
+                .rev()
+                .find(|i| i.count > 0)
+                .map(|i| i.value);
+        }
+        None
+    }
+
+    /// quantile_for_count returns the value of the first bin where the cumulative count
+    /// reaches `target_count`, for queries phrased as a count rather than a fraction, e.g.
+    /// the value below which 9500 of the requests fall. Returns None when the histogram
+    /// holds fewer than `target_count` values.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(100);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.quantile_for_count(95), Some(95.0));
+    ///  assert_eq!(histogram.quantile_for_count(101), None);
+    /// ```
+    pub fn quantile_for_count(&self, target_count: u64) -> Option<f64> {
+        let mut count: u64 = 0;
+        for i in self.bins.iter() {
+            count = count.saturating_add(i.count as u64);
+            if count >= target_count && i.count > 0 {
+                return Some(i.value);
+            }
+        }
+        None
+    }
+
+    /// mass_between_quantiles returns the fraction of the values lying between the `q_low`
+    /// and the `q_high` quantile, both included, counted by bin: the bins whose value lies in
+    /// `[quantile(q_low), quantile(q_high)]`. By definition this is about `q_high - q_low`,
+    /// but each quantile is the value of the bin where it falls, so both boundary bins count
+    /// entirely and the result is at least `q_high - q_low` and larger by up to their share
+    /// of the total. The difference shows how coarse the bins are around the band. Returns
+    /// None when the histogram is empty or the quantiles are not `0 <= q_low <= q_high <= 1`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(100);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  // 25.0 to 75.0, both included
+    ///  assert_eq!(histogram.mass_between_quantiles(0.25, 0.75), Some(0.51));
+    /// ```
+    pub fn mass_between_quantiles(&self, q_low: f64, q_high: f64) -> Option<f64> {
+        if !(0.0 <= q_low && q_low <= q_high && q_high <= 1.0) {
+            return None;
+        }
+        let low = self.quantile(q_low)?;
+        let high = self.quantile(q_high)?;
+        let count: u64 = self
+            .bins
+            .iter()
+            .filter(|i| low <= i.value && i.value <= high)
+            .map(|i| i.count as u64)
+            .sum();
+        Some(count as f64 / self.total as f64)
+    }
+
+    /// meets_slo check whether the q-quantile is under the `limit`, for example whether
+    /// the p99 latency stays within 100ms. Returns None when the histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.meets_slo(0.99, 100.0), Some(true));
+    ///  assert_eq!(histogram.meets_slo(0.5, 10.0), Some(false));
+    /// ```
+    pub fn meets_slo(&self, quantile: f64, limit: f64) -> Option<bool> {
+        if self.total == 0 {
+            return None;
+        }
+        Some(self.quantile(quantile)? <= limit)
+    }
+
+    /// weighted_quantile is a more precise version of [`Histogram::quantile`], instead of
+    /// returning the value of the bin where the cumulative count crosses `q * total`, it
+    /// interpolates inside that bin using the fraction of its count needed to reach the target.
+    /// Each bin is assumed to spread evenly between the midpoints to its neighbors
+    /// (the minimum and maximum value for the first and last bin). The spread each bin keeps
+    /// for [`Histogram::variance`] is not used here, the ranges only depend on the bin
+    /// positions, so tracking it improves the variance but not the interpolated quantiles.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let median = histogram.weighted_quantile(0.5).unwrap();
+    ///  assert!((median - 50.5).abs() < 1.0);
+    /// ```
+    pub fn weighted_quantile(&self, q: f64) -> Option<f64> {
+        if self.total == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let target = q * self.total as f64;
+        let mut count = 0.0;
+        for (i, (low, high)) in self.bins.iter().zip(self.bin_bounds()) {
+            let next = count + i.count as f64;
+            if next >= target && i.count > 0 {
+                let fraction = (target - count) / i.count as f64;
+                return Some(low + (high - low) * fraction);
+            }
+            count = next;
+        }
+        self.max
+    }
+
+    /// interpolated_percentiles returns [`Histogram::weighted_quantile`] for each of `qs`, in
+    /// the order they are requested, walking the bins only once. Quantiles outside
+    /// `0.0..=1.0` give None, like all of them when the histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let percentiles = histogram.interpolated_percentiles(&[0.99, 0.5, 2.0]);
+    ///  assert_eq!(percentiles[0], histogram.weighted_quantile(0.99));
+    ///  assert_eq!(percentiles[1], histogram.weighted_quantile(0.5));
+    ///  assert_eq!(percentiles[2], None);
+    /// ```
+    pub fn interpolated_percentiles(&self, qs: &[f64]) -> Vec<Option<f64>> {
+        let mut result = vec![None; qs.len()];
+        if self.total == 0 {
+            return result;
+        }
+        let mut targets: Vec<(f64, usize)> = qs
+            .iter()
+            .enumerate()
+            .filter(|(_, q)| (0.0..=1.0).contains(*q))
+            .map(|(index, q)| (q * self.total as f64, index))
+            .collect();
+        targets.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut pending = targets.iter().peekable();
+        let mut count = 0.0;
+        for (i, (low, high)) in self.bins.iter().zip(self.bin_bounds()) {
+            let next = count + i.count as f64;
+            if i.count > 0 {
+                while let Some((target, index)) = pending.next_if(|(target, _)| next >= *target) {
+                    let fraction = (target - count) / i.count as f64;
+                    result[*index] = Some(low + (high - low) * fraction);
+                }
+            }
+            count = next;
+        }
+        for (_, index) in pending {
+            result[*index] = self.max;
+        }
+        result
+    }
+
+    // bin_bounds returns the value range represented by each bin, the boundary
+    // between two bins is the midpoint of their values, the first and last bin
+    // extend to the minimum and maximum value. Zero-count anchors hold no values,
+    // they get an empty range at their value and don't move the boundaries of
+    // their neighbours.
+    fn bin_bounds(&self) -> Vec<(f64, f64)> {
+        let values: Vec<f64> = self
+            .bins
+            .iter()
+            .filter(|i| i.count > 0)
+            .map(|i| i.value)
+            .collect();
+        let mut bounds = Vec::with_capacity(self.bins.len());
+        let mut index = 0;
+        for i in self.bins.iter() {
+            if i.count == 0 {
+                bounds.push((i.value, i.value));
+                continue;
+            }
+            let value = values[index];
+            let low = if index == 0 {
+                self.min.unwrap_or(value)
+            } else {
+                (values[index - 1] + value) / 2.0
+            };
+            let high = if index + 1 == values.len() {
+                self.max.unwrap_or(value)
+            } else {
+                (value + values[index + 1]) / 2.0
+            };
+            bounds.push((low, high));
+            index += 1;
+        }
+        bounds
+    }
+
+    /// quantile_interval returns the interval `[low, high]` which contains the q-quantile
+    /// instead of a single point estimate. `high` is the bin value returned by
+    /// [`Histogram::quantile`] and `low` is the value of the bin just before it (or the minimum
+    /// value when the quantile falls in the first bin), so the width of the interval shows
+    /// the uncertainty of the approximation.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let (low, high) = histogram.quantile_interval(0.5).unwrap();
+    ///  assert!(low <= 50.5 && 50.5 <= high);
+    /// ```
+    pub fn quantile_interval(&self, q: f64) -> Option<(f64, f64)> {
+        let rank = target_rank(q, self.total)?;
+        let mut count: u64 = 0;
+        let mut low = self.min?;
+        for i in self.bins.iter().filter(|i| i.count > 0) {
+            count = count.saturating_add(i.count as u64);
+            if count >= rank {
+                return Some((low, i.value));
+            }
+            low = i.value;
+        }
+        None
+    }
+
+    /// Cumulative distribution function(aka: cdf) returns the value of the cumulative
+    /// distribution at value x. for more detail, please check [wikipedia](https://en.wikipedia.org/wiki/Cumulative_distribution_function)
+    #[allow(dead_code)]
+    pub fn cdf(&self, x: f64) -> Option<f64> {
+        let mut count = 0;
+        for i in self.bins.iter() {
+            if i.value <= x {
+                count += i.count
+            }
+        }
+        if self.total == 0 {
+            None
+        } else {
+            Some(count as f64 / self.total as f64)
+        }
+    }
+
+    /// cdf_batch returns [`Histogram::cdf`] for each of `xs`, in the order they are given,
+    /// sorting the points and walking the bins only once, which suits plotting a whole
+    /// curve. NaN points and an empty histogram give None.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=4 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.cdf_batch(&[3.0, 0.0]), vec![Some(0.75), Some(0.0)]);
+    /// ```
+    pub fn cdf_batch(&self, xs: &[f64]) -> Vec<Option<f64>> {
+        let mut result = vec![None; xs.len()];
+        if self.total == 0 {
+            return result;
+        }
+        let mut points: Vec<(f64, usize)> = xs
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !x.is_nan())
+            .map(|(index, x)| (*x, index))
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut bins = self.bins.iter().peekable();
+        let mut count: u64 = 0;
+        for (x, index) in points {
+            while let Some(i) = bins.next_if(|i| i.value <= x) {
+                count += i.count as u64;
+            }
+            result[index] = Some(count as f64 / self.total as f64);
+        }
+        result
+    }
+
+    /// survival returns the fraction of values greater than `x`, `1 - cdf(x)`, the survival
+    /// function used for tail curves. Returns None when the histogram is empty or `x` is NaN.
+    pub fn survival(&self, x: f64) -> Option<f64> {
+        if x.is_nan() {
+            return None;
+        }
+        self.survival_batch(&[x]).pop()?
+    }
+
+    /// survival_batch returns [`Histogram::survival`] for each of `xs`, in the order they are
+    /// given, counting from the highest bin down in a single pass. At every point it adds up
+    /// to 1 with [`Histogram::cdf_batch`]. NaN points and an empty histogram give None.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=4 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.survival_batch(&[3.0, 0.0]), vec![Some(0.25), Some(1.0)]);
+    /// ```
+    pub fn survival_batch(&self, xs: &[f64]) -> Vec<Option<f64>> {
+        let mut result = vec![None; xs.len()];
+        if self.total == 0 {
+            return result;
+        }
+        let mut points: Vec<(f64, usize)> = xs
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !x.is_nan())
+            .map(|(index, x)| (*x, index))
+            .collect();
+        points.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut bins = self.bins.iter().rev().peekable();
+        let mut count: u64 = 0;
+        for (x, index) in points {
+            while let Some(i) = bins.next_if(|i| i.value > x) {
+                count += i.count as u64;
+            }
+            result[index] = Some(count as f64 / self.total as f64);
+        }
+        result
+    }
+
+    /// ratio_below returns the fraction of values below `x` like [`Histogram::cdf`], but
+    /// instead of a step at each bin value the count of the bin containing `x` is linearly
+    /// interpolated, each bin spreads evenly over the range described in
+    /// [`Histogram::weighted_quantile`]. The result is smooth in `x`, which suits SLO
+    /// dashboards. Returns None when the histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let below = histogram.ratio_below(50.5).unwrap();
+    ///  assert!((below - 0.5).abs() < 0.02);
+    ///  assert_eq!(below + histogram.ratio_above(50.5).unwrap(), 1.0);
+    /// ```
+    pub fn ratio_below(&self, x: f64) -> Option<f64> {
+        if self.total == 0 || x.is_nan() {
+            return None;
+        }
+        let mut count = 0.0;
+        for (i, (low, high)) in self.bins.iter().zip(self.bin_bounds()) {
+            if x >= high {
+                count += i.count as f64;
+            } else if x > low {
+                count += i.count as f64 * (x - low) / (high - low);
+            }
+        }
+        Some((count / self.total as f64).min(1.0))
+    }
+
+    /// ratio_above returns the interpolated fraction of values above `x`, the complement of
+    /// [`Histogram::ratio_below`], so both always sum to 1. Returns None when empty.
+    pub fn ratio_above(&self, x: f64) -> Option<f64> {
+        self.ratio_below(x).map(|below| 1.0 - below)
+    }
+
+    /// density returns the estimated probability density at `x`: each bin spreads its share of
+    /// the total evenly over its range, as in [`Histogram::ratio_below`], so the density is a
+    /// step function which integrates to 1. It is 0 outside `[min, max]`, and a bin of zero
+    /// width (e.g. when all values are equal) is a point mass without a density. Returns None
+    /// when the histogram is empty.
+    pub fn density(&self, x: f64) -> Option<f64> {
+        if self.total == 0 || x.is_nan() {
+            return None;
+        }
+        let bounds = self.bin_bounds();
+        let last = bounds.len() - 1;
+        for (index, (i, (low, high))) in self.bins.iter().zip(bounds).enumerate() {
+            if high > low && low <= x && (x < high || (index == last && x == high)) {
+                return Some(i.count as f64 / self.total as f64 / (high - low));
+            }
+        }
+        Some(0.0)
+    }
+
+    /// overlap returns the overlapping coefficient of both distributions, the integral of
+    /// `min(density_self(x), density_other(x))` over all x (see [`Histogram::density`]). It is
+    /// inside `[0, 1]`: 1 for identical histograms and 0 when the values don't overlap at all.
+    /// Both densities are constant between the bin boundaries of both histograms, so the
+    /// integral is the sum over these intervals of the smaller of both masses, point masses
+    /// at the same value overlap too. Returns None when either histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut first = Histogram::new(20);
+    ///  let mut second = Histogram::new(20);
+    ///  for i in 0..100 {
+    ///       first.add(i as f64);
+    ///       second.add(i as f64 + 50.0);
+    ///  }
+    ///  assert!((first.overlap(&first).unwrap() - 1.0).abs() < 1e-9);
+    ///  assert!((first.overlap(&second).unwrap() - 0.5).abs() < 0.05);
+    /// ```
+    pub fn overlap(&self, other: &Histogram) -> Option<f64> {
+        if self.total == 0 || other.total == 0 {
+            return None;
+        }
+        let mut points: Vec<f64> = self
+            .bin_bounds()
+            .into_iter()
+            .chain(other.bin_bounds())
+            .flat_map(|(low, high)| vec![low, high])
+            .collect();
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        points.dedup();
+        let first = self.ratios_below(&points);
+        let second = other.ratios_below(&points);
+        let (mut last, mut other_last) = (0.0, 0.0);
+        let mut overlap = 0.0;
+        for (below, other_below) in first.into_iter().zip(second) {
+            overlap += f64::min(below - last, other_below - other_last);
+            last = below;
+            other_last = other_below;
+        }
+        Some(overlap.clamp(0.0, 1.0))
+    }
+
+    /// decile_mass divides the `[min, max]` range into 10 bands of equal width and returns the
+    /// fraction of the total count falling into each band, from the lowest to the highest.
+    /// Unlike deciles, which split the count into equal parts, the bands split the values, so
+    /// the fractions show where the mass sits. Bins are spread evenly over their range as in
+    /// [`Histogram::ratio_below`], and the fractions sum to 1. Returns an empty vector when
+    /// the histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(0.0, 3);
+    ///  histogram.add(100.0);
+    ///  let mass = histogram.decile_mass();
+    ///  assert_eq!(mass.len(), 10);
+    ///  assert!(mass[0] > mass[9]);
+    /// ```
+    pub fn decile_mass(&self) -> Vec<f64> {
+        let (min, max) = match (self.min, self.max) {
+            (Some(min), Some(max)) if self.total > 0 => (min, max),
+            _ => return Vec::new(),
+        };
+        let edges: Vec<f64> = (1..=10)
+            .map(|k| min + (max - min) * k as f64 / 10.0)
+            .collect();
+        let mut last = 0.0;
+        self.ratios_below(&edges)
+            .into_iter()
+            .map(|below| {
+                let mass = below - last;
+                last = below;
+                mass
+            })
+            .collect()
+    }
+
+    // ratios_below returns ratio_below for each of the sorted `points`, in a single pass
+    // over the bins. The anchors are skipped, their empty ranges lie inside the ranges of
+    // their neighbours.
+    fn ratios_below(&self, points: &[f64]) -> Vec<f64> {
+        let bins: Vec<(usize, (f64, f64))> = self
+            .bins
+            .iter()
+            .map(|i| i.count)
+            .zip(self.bin_bounds())
+            .filter(|(count, _)| *count > 0)
+            .collect();
+        let mut ratios = Vec::with_capacity(points.len());
+        let (mut index, mut full) = (0, 0.0);
+        for x in points {
+            while index < bins.len() && *x >= (bins[index].1).1 {
+                full += bins[index].0 as f64;
+                index += 1;
+            }
+            let mut count = full;
+            if let Some((partial, (low, high))) = bins.get(index) {
+                if x > low {
+                    count += *partial as f64 * (x - low) / (high - low);
+                }
+            }
+            ratios.push((count / self.total as f64).min(1.0));
+        }
+        ratios
+    }
+
+    /// qq_points returns `n` matched quantile pairs `(self.quantile(i / n), other.quantile(i / n))`
+    /// for `i` in `1..=n`, the data of a Q-Q plot: points on the line `y = x` mean both
+    /// distributions agree, and the way the points bend away shows a shift, a different spread
+    /// or heavier tails. Returns an empty vector when either histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut first = Histogram::new(10);
+    ///  let mut second = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       first.add(i as f64);
+    ///       second.add(i as f64 * 2.0);
+    ///  }
+    ///  let points = first.qq_points(&second, 4);
+    ///  assert_eq!(points.len(), 4);
+    ///  assert!(points.iter().all(|(x, y)| y > x));
+    /// ```
+    pub fn qq_points(&self, other: &Histogram, n: usize) -> Vec<(f64, f64)> {
+        if self.total == 0 || other.total == 0 {
+            return Vec::new();
+        }
+        (1..=n)
+            .filter_map(|i| {
+                let q = i as f64 / n as f64;
+                Some((self.quantile(q)?, other.quantile(q)?))
+            })
+            .collect()
+    }
+
+    /// cdf_area_distance returns the area between the cumulative distribution functions of
+    /// both histograms, the integral of `|cdf_self(x) - cdf_other(x)|` over all x. It is
+    /// symmetric, 0 for identical histograms and grows with the shift between two
+    /// distributions of the same shape (it's the earth mover's distance), in the unit of
+    /// the values. Both CDFs are steps at the bin values, so the integral is computed exactly
+    /// over the union of the bin values of both histograms, where the trapezoidal rule and the
+    /// step sum agree. Returns None when either histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut first = Histogram::new(20);
+    ///  let mut second = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       first.add(i as f64);
+    ///       second.add(i as f64 + 5.0);
+    ///  }
+    ///  assert!((first.cdf_area_distance(&second).unwrap() - 5.0).abs() < 1e-9);
+    /// ```
+    pub fn cdf_area_distance(&self, other: &Histogram) -> Option<f64> {
+        if self.total == 0 || other.total == 0 {
+            return None;
+        }
+        let (total, other_total) = (self.total as f64, other.total as f64);
+        let mut first = self.iter_bins().peekable();
+        let mut second = other.iter_bins().peekable();
+        let (mut cdf, mut other_cdf): (f64, f64) = (0.0, 0.0);
+        let mut last: Option<f64> = None;
+        let mut area = 0.0;
+        loop {
+            let x = match (first.peek(), second.peek()) {
+                (Some(a), Some(b)) => a.0.min(b.0),
+                (Some(a), None) => a.0,
+                (None, Some(b)) => b.0,
+                (None, None) => break,
+            };
+            if let Some(last) = last {
+                area += (cdf - other_cdf).abs() * (x - last);
+            }
+            while let Some((_, count)) = first.next_if(|i| i.0 == x) {
+                cdf += count as f64 / total;
+            }
+            while let Some((_, count)) = second.next_if(|i| i.0 == x) {
+                other_cdf += count as f64 / other_total;
+            }
+            last = Some(x);
+        }
+        Some(area)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.total == 0 {
+            return None;
+        }
+        Some(self.moments.mean)
+    }
+
+    /// centroid returns the count-weighted average of the current bin values, which describes
+    /// the bins rather than the original samples. Merging two bins keeps their weighted average
+    /// so the centroid equals [`Histogram::mean`] while the counts are exact, a difference
+    /// between them shows how much the bins drifted from the data (for example after
+    /// counts saturated). Returns None when the histogram is empty.
+    pub fn centroid(&self) -> Option<f64> {
+        let mut sum = 0.0;
+        let mut count = 0.0;
+        for i in self.bins.iter() {
+            sum += i.value * i.count as f64;
+            count += i.count as f64;
+        }
+        if count == 0.0 {
+            return None;
+        }
+        Some(sum / count)
+    }
+    /// variance returns the population variance. Every bin keeps the spread of the values
+    /// merged into it, so the variance stays exact across merges, only splitting or
+    /// rescaling bins makes it approximate. Like the mean it is kept up to date on every add
+    /// and merge, so reading it is O(1).
+    #[allow(dead_code)]
+    pub fn variance(&self) -> Option<f64> {
+        if self.total == 0 {
+            return None;
+        }
+        Some(self.moments.m2 / self.moments.count)
+    }
+
+    /// trimmed_mean returns the mean of the values between the `trim_fraction` and
+    /// `1 - trim_fraction` quantiles, so outliers in both tails are ignored. A bin straddling
+    /// a cut counts with the part of its count inside. `trim_fraction` is clamped into
+    /// `[0, 0.5)`, 0 gives [`Histogram::mean`]. Returns None when the histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=9 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  histogram.add(1000.0);
+    ///  assert_eq!(histogram.trimmed_mean(0.1), Some(5.5));
+    /// ```
+    pub fn trimmed_mean(&self, trim_fraction: f64) -> Option<f64> {
+        weighted_mean(&self.trimmed_bins(trim_fraction)?)
+    }
+
+    /// trimmed_variance returns the variance of the values kept by
+    /// [`Histogram::trimmed_mean`], about the trimmed mean, so outliers barely affect it.
+    /// The spread inside the kept bins is included in proportion to the part of each bin
+    /// which is kept. `trim_fraction` is clamped into `[0, 0.5)`. Returns None when the
+    /// histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=9 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  histogram.add(1000.0);
+    ///  assert!((histogram.trimmed_variance(0.1).unwrap() - 5.25).abs() < 1e-9);
+    /// ```
+    pub fn trimmed_variance(&self, trim_fraction: f64) -> Option<f64> {
+        let bins = self.trimmed_bins(trim_fraction)?;
+        let mean = weighted_mean(&bins)?;
+        let (weight, sum) = bins.iter().fold((0.0, 0.0), |(w, s), (i, weight)| {
+            let spread = i.m2 * weight / i.count as f64;
+            (
+                w + weight,
+                s + weight * (i.value - mean) * (i.value - mean) + spread,
+            )
+        });
+        Some(sum / weight)
+    }
+
+    // trimmed_bins returns the bins between the trim quantiles with the part of their
+    // count inside, None when empty.
+    fn trimmed_bins(&self, trim_fraction: f64) -> Option<Vec<(&Bin, f64)>> {
+        if self.total == 0 {
+            return None;
+        }
+        let trim = if trim_fraction >= 0.5 {
+            0.5 - f64::EPSILON
+        } else {
+            trim_fraction.max(0.0)
+        };
+        let lower = trim * self.total as f64;
+        Some(self.bins_between(lower, self.total as f64 - lower))
+    }
+
+    // bins_between returns the bins holding the values ranked between `lower` and `upper`
+    // with the part of their count inside.
+    fn bins_between(&self, lower: f64, upper: f64) -> Vec<(&Bin, f64)> {
+        let mut seen = 0.0;
+        let mut bins = Vec::new();
+        for i in self.bins.iter() {
+            let next = seen + i.count as f64;
+            let weight = next.min(upper) - seen.max(lower);
+            if weight > 0.0 {
+                bins.push((i, weight));
+            }
+            seen = next;
+        }
+        bins
+    }
+
+    /// conditional_mean_above returns the mean of the values above the q-quantile, the tail
+    /// expectation behind the Conditional Value at Risk (CVaR). It is the count-weighted mean
+    /// of the bins holding the top `1 - q` of the values, a bin straddling the quantile counts
+    /// with the part of its count above it. With `q` equal to 1 no value is left above and the
+    /// maximum is returned. Returns None when the histogram is empty or `q` is outside
+    /// `0.0..=1.0`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=10 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.conditional_mean_above(0.8), Some(9.5));
+    /// ```
+    pub fn conditional_mean_above(&self, q: f64) -> Option<f64> {
+        if self.total == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let total = self.total as f64;
+        weighted_mean(&self.bins_between(q * total, total)).or(self.max)
+    }
+
+    /// conditional_mean_below returns the mean of the values below the q-quantile, the lower
+    /// tail counterpart of [`Histogram::conditional_mean_above`]: the count-weighted mean of
+    /// the bins holding the bottom `q` of the values. With `q` equal to 0 the minimum is
+    /// returned. Returns None when the histogram is empty or `q` is outside `0.0..=1.0`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=10 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.conditional_mean_below(0.2), Some(1.5));
+    /// ```
+    pub fn conditional_mean_below(&self, q: f64) -> Option<f64> {
+        if self.total == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        weighted_mean(&self.bins_between(0.0, q * self.total as f64)).or(self.min)
+    }
+
+    /// stddev returns the standard deviation, which is the square root of the variance.
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// coefficient_of_variation returns `stddev / mean`, which compares the spread of
+    /// distributions with different scales. Returns None when the histogram is empty
+    /// or the mean is zero (or near zero) where the ratio is meaningless.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let cv = histogram.coefficient_of_variation().unwrap();
+    ///  assert!(cv > 0.5 && cv < 0.6);
+    /// ```
+    pub fn coefficient_of_variation(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        if mean.abs() < f64::EPSILON {
+            return None;
+        }
+        Some(self.stddev()? / mean)
+    }
+
+    /// to_hdr_buckets maps the bins onto the log-linear bucket layout used by HdrHistogram,
+    /// so the data can be consumed by HdrHistogram tools. `lowest` is the lowest discernible
+    /// value (the width of the first buckets), `highest` the highest trackable value and
+    /// `sig_figs` the number of significant decimal digits (1 to 5) kept by the buckets.
+    /// It returns the lower boundary and the count of every non-empty bucket in ascending order.
+    ///
+    /// The original values are not kept by the histogram, so the whole count of each bin is
+    /// put into the bucket containing the bin value, values below zero or above `highest`
+    /// are clamped into the range. An invalid layout returns an empty vector.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=1000 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let buckets = histogram.to_hdr_buckets(1.0, 1000.0, 2);
+    ///  assert_eq!(buckets.iter().map(|(_, count)| count).sum::<u64>(), 1000);
+    /// ```
+    pub fn to_hdr_buckets(&self, lowest: f64, highest: f64, sig_figs: u8) -> Vec<(f64, u64)> {
+        if !(lowest > 0.0 && highest >= 2.0 * lowest && (1..=5).contains(&sig_figs)) {
+            return Vec::new();
+        }
+        // the first bucket has `sub_buckets` linear sub-buckets of width `lowest`,
+        // every following bucket has half as many sub-buckets with doubled width.
+        let sub_buckets = (2.0 * 10f64.powi(sig_figs as i32)).log2().ceil().exp2();
+        let mut buckets: Vec<(f64, u64)> = Vec::new();
+        for i in self.bins.iter().filter(|i| i.count > 0) {
+            let value = i.value.max(0.0).min(highest);
+            let units = value / lowest;
+            let width = if units < sub_buckets {
+                lowest
+            } else {
+                lowest * ((units / sub_buckets).log2().floor() + 1.0).exp2()
+            };
+            let lower = (value / width).floor() * width;
+            match buckets.last_mut() {
+                Some(last) if last.0 == lower => last.1 += i.count as u64,
+                _ => buckets.push((lower, i.count as u64)),
+            }
+        }
+        buckets
+    }
+
+    /// to_prometheus_buckets returns the cumulative Prometheus style buckets for the upper
+    /// bounds `bounds`: each `(le, count)` pair counts the values less than or equal to `le`.
+    /// The bounds are sorted and deduplicated, NaN and infinite bounds are skipped (the `+Inf`
+    /// bucket is the total). The whole count of a bin is attributed to its value.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(100);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.to_prometheus_buckets(&[10.0, 100.0]), vec![(10.0, 10), (100.0, 100)]);
+    /// ```
+    pub fn to_prometheus_buckets(&self, bounds: &[f64]) -> Vec<(f64, u64)> {
+        let mut bounds: Vec<f64> = bounds.iter().copied().filter(|b| b.is_finite()).collect();
+        bounds.sort_by(|a, b| a.total_cmp(b));
+        bounds.dedup();
+        let mut bins = self.bins.iter().peekable();
+        let mut count: u64 = 0;
+        bounds
+            .into_iter()
+            .map(|le| {
+                while let Some(i) = bins.next_if(|i| i.value <= le) {
+                    count = count.saturating_add(i.count as u64);
+                }
+                (le, count)
+            })
+            .collect()
+    }
+
+    /// to_openmetrics renders the histogram in the OpenMetrics text exposition format,
+    /// ready to be served by a metrics endpoint: a `# TYPE` line, one cumulative
+    /// `<metric_name>_bucket{le="..."}` line per bound (see
+    /// [`Histogram::to_prometheus_buckets`]) plus the `+Inf` bucket, the `_sum` and `_count`
+    /// lines. The closing `# EOF` is left to the caller, so the text of several histograms
+    /// can be concatenated into one exposition ended by a single `# EOF` line.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  histogram.add(0.5);
+    ///  histogram.add(2.0);
+    ///  let text = histogram.to_openmetrics("latency_seconds", &[1.0]);
+    ///  assert!(text.starts_with("# TYPE latency_seconds histogram\n"));
+    ///  assert!(text.contains("latency_seconds_bucket{le=\"1.0\"} 1\n"));
+    ///  assert!(text.contains("latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+    ///  assert!(text.contains("latency_seconds_sum 2.5\n"));
+    ///  assert!(text.contains("latency_seconds_count 2\n"));
+    ///  assert!(!text.contains("# EOF"));
+    /// ```
+    pub fn to_openmetrics(&self, metric_name: &str, bounds: &[f64]) -> String {
+        let mut text = format!("# TYPE {} histogram\n", metric_name);
+        for (le, count) in self.to_prometheus_buckets(bounds) {
+            text += &format!("{}_bucket{{le=\"{:?}\"}} {}\n", metric_name, le, count);
+        }
+        let sum = self
+            .bins
+            .iter()
+            .fold(0.0, |sum, i| sum + i.value * i.count as f64);
+        text += &format!("{}_bucket{{le=\"+Inf\"}} {}\n", metric_name, self.total);
+        text += &format!("{}_sum {:?}\n", metric_name, sum);
+        text += &format!("{}_count {}\n", metric_name, self.total);
+        text
+    }
+
+    /// pmf returns the probability mass of each bin as `(value, count / total)` pairs,
+    /// the weights sum to 1.0. Returns an empty vector when the histogram is empty.
+    pub fn pmf(&self) -> Vec<(f64, f64)> {
+        if self.total == 0 {
+            return Vec::new();
+        }
+        self.bins
+            .iter()
+            .map(|i| (i.value, i.count as f64 / self.total as f64))
+            .collect()
+    }
+
+    /// bucket_percentages returns the share of each bin in the total as a percentage,
+    /// `(value, count / total * 100)` pairs which sum to 100. Returns an empty vector when
+    /// the histogram is empty, see [`Histogram::pmf`] for the fractions.
+    pub fn bucket_percentages(&self) -> Vec<(f64, f64)> {
+        self.pmf()
+            .into_iter()
+            .map(|(value, weight)| (value, weight * 100.0))
+            .collect()
+    }
+
+    /// normalized_shape returns the shape of the distribution independent of its scale,
+    /// the `[min, max]` range is divided into 32 cells of equal width and each cell holds
+    /// the fraction of values whose bin falls into it, so the vector sums to 1.
+    /// Returns an empty vector when the histogram is empty.
+    pub fn normalized_shape(&self) -> Vec<f64> {
+        const GRID: usize = 32;
+        let (min, max) = match (self.min, self.max) {
+            (Some(min), Some(max)) if self.total > 0 => (min, max),
+            _ => return Vec::new(),
+        };
+        let mut shape = vec![0.0; GRID];
+        for i in self.bins.iter() {
+            let position = if max > min {
+                (i.value - min) / (max - min)
+            } else {
+                0.0
+            };
+            let cell = ((position * GRID as f64) as usize).min(GRID - 1);
+            shape[cell] += i.count as f64 / self.total as f64;
+        }
+        shape
+    }
+
+    /// shape_distance compares the shape of two distributions regardless of their units,
+    /// it returns the total variation distance between their [`Histogram::normalized_shape`],
+    /// 0 means the same shape and 1 completely different shapes. Returns None if any of
+    /// the histograms is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut seconds = Histogram::new(20);
+    ///  let mut millis = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       seconds.add((i % 10) as f64);
+    ///       millis.add((i % 10) as f64 * 1000.0);
+    ///  }
+    ///  assert!(seconds.shape_distance(&millis).unwrap() < 1e-9);
+    /// ```
+    pub fn shape_distance(&self, other: &Histogram) -> Option<f64> {
+        let first = self.normalized_shape();
+        let second = other.normalized_shape();
+        if first.is_empty() || second.is_empty() {
+            return None;
+        }
+        let sum: f64 = first
+            .iter()
+            .zip(second.iter())
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        Some(sum / 2.0)
+    }
+
+    /// significant_bins returns the `(value, count)` of bins holding at least `min_fraction`
+    /// of the total count, sorted by descending count, which filters the noise when
+    /// looking for hotspots.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add((i % 3) as f64);
+    ///  }
+    ///  assert_eq!(histogram.significant_bins(0.34), vec![(1.0, 34)]);
+    /// ```
+    pub fn significant_bins(&self, min_fraction: f64) -> Vec<(f64, usize)> {
+        if self.total == 0 {
+            return Vec::new();
+        }
+        let mut bins: Vec<(f64, usize)> = self
+            .bins
+            .iter()
+            .filter(|i| i.count as f64 / self.total as f64 >= min_fraction)
+            .map(|i| (i.value, i.count))
+            .collect();
+        bins.sort_by_key(|i| std::cmp::Reverse(i.1));
+        bins
+    }
+
+    /// summary_dto returns a [`SummaryDto`] with the count, mean, min, max, variance and the
+    /// 0.5, 0.9 and 0.99 quantiles, see [`Histogram::summary_dto_with`] for other quantiles.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let dto = histogram.summary_dto();
+    ///  assert_eq!(dto.count, 100);
+    ///  assert_eq!(dto.percentiles[0], (0.5, histogram.quantile(0.5).unwrap()));
+    /// ```
+    pub fn summary_dto(&self) -> SummaryDto {
+        self.summary_dto_with(&[0.5, 0.9, 0.99])
+    }
+
+    /// summary_dto_with returns a [`SummaryDto`] holding the requested `percentiles`, given as
+    /// quantiles inside `0.0..=1.0` and computed like [`Histogram::quantile`]. Invalid
+    /// quantiles are left out.
+    pub fn summary_dto_with(&self, percentiles: &[f64]) -> SummaryDto {
+        let summary = self.summarize(percentiles);
+        SummaryDto {
+            count: self.total,
+            mean: self.mean(),
+            min: self.min,
+            max: self.max,
+            variance: self.variance(),
+            percentiles: percentiles
+                .iter()
+                .zip(summary.quantiles)
+                .filter_map(|(q, value)| Some((*q, value?)))
+                .collect(),
+        }
+    }
+
+    /// summarize computes the count, mean, variance, min, max and the requested quantiles
+    /// walking the bins only once, instead of traversing them for every statistic. The mean
+    /// and variance are the running moments of [`Histogram::mean`] and [`Histogram::variance`].
+    /// The quantiles are returned in the order they are requested and match [`Histogram::quantile`].
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let summary = histogram.summarize(&[0.5, 0.9, 0.99]);
+    ///  assert_eq!(summary.mean, Some(50.5));
+    ///  assert_eq!(summary.quantiles[0], histogram.quantile(0.5));
+    /// ```
+    pub fn summarize(&self, quantiles: &[f64]) -> Summary {
+        let mut result = vec![None; quantiles.len()];
+        let mut ranks: Vec<(u64, usize)> = quantiles
+            .iter()
+            .enumerate()
+            .filter_map(|(index, q)| target_rank(*q, self.total).map(|rank| (rank, index)))
+            .collect();
+        ranks.sort_unstable();
+        let mut pending = ranks.iter().peekable();
+        let mut count: u64 = 0;
+        for i in self.bins.iter() {
+            count = count.saturating_add(i.count as u64);
+            while let Some((_, index)) = pending.next_if(|(rank, _)| count >= *rank) {
+                result[*index] = Some(i.value);
+            }
+        }
+        Summary {
+            count: self.total,
+            mean: self.mean(),
+            variance: self.variance(),
+            min: self.min,
+            max: self.max,
+            quantiles: result,
+        }
+    }
+
+    /// gini returns the Gini coefficient of the values, 0 when all values are equal and
+    /// close to 1 when the sum is concentrated on a few of them. It is computed from the
+    /// Lorenz curve of the sorted bins weighted by their count:
+    /// `1 - Σ (count_i / total) * (L_(i-1) + L_i)`, where `L_i` is the share of the sum held
+    /// by the bins up to `i`. The coefficient is only defined for non-negative values, so None
+    /// is returned when the histogram is empty, holds negative values or all values are zero.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(0.0, 9);
+    ///  histogram.add(100.0);
+    ///  assert!((histogram.gini().unwrap() - 0.9).abs() < 1e-9);
+    /// ```
+    pub fn gini(&self) -> Option<f64> {
+        if self.total == 0 || self.min? < 0.0 {
+            return None;
+        }
+        let count: f64 = self.bins.iter().map(|i| i.count as f64).sum();
+        let sum: f64 = self.bins.iter().map(|i| i.value * i.count as f64).sum();
+        if sum <= 0.0 {
+            return None;
+        }
+        let mut area = 0.0;
+        let mut share = 0.0;
+        for i in self.bins.iter() {
+            let next = share + i.value * i.count as f64 / sum;
+            area += i.count as f64 / count * (share + next);
+            share = next;
+        }
+        Some(1.0 - area)
+    }
+
+    /// labeled_bins returns each bin as a `(label, count)` pair in ascending order, the label
+    /// is the value range the bin stands for, as used by [`Histogram::ratio_below`]: it runs
+    /// between the midpoints to the neighbouring bins, and the first and last bin start and
+    /// end at the minimum and maximum value. Ranges are half open, `"[low, high)"`, except the
+    /// last one which includes the maximum, `"[low, high]"`. The zero-count anchors of
+    /// [`Histogram::new_with_range`] get the empty range `"[value, value)"`. This suits
+    /// plotting libraries which label bars with ranges.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(10.0);
+    ///  histogram.add(15.0);
+    ///  histogram.add(15.0);
+    ///  assert_eq!(
+    ///       histogram.labeled_bins(),
+    ///       vec![
+    ///           (String::from("[10.0, 12.5)"), 1),
+    ///           (String::from("[12.5, 15.0]"), 2)
+    ///       ]
+    ///  );
+    /// ```
+    pub fn labeled_bins(&self) -> Vec<(String, usize)> {
+        // the range of the last bin holding values is closed, anchors after it are empty
+        let last = self
+            .bins
+            .iter()
+            .rposition(|i| i.count > 0)
+            .unwrap_or(self.bins.len().saturating_sub(1));
+        self.bins
+            .iter()
+            .zip(self.bin_bounds())
+            .enumerate()
+            .map(|(index, (i, (low, high)))| {
+                let close = if index == last { ']' } else { ')' };
+                (format!("[{:?}, {:?}{}", low, high, close), i.count)
+            })
+            .collect()
+    }
+
+    /// fingerprint returns a hash of the content: `max_bins`, the total and the value and
+    /// count of every bin. It is computed with FNV-1a over the little endian bytes, so it is
+    /// the same across runs, platforms and compiler versions and can be used as a cache key or
+    /// to detect changes. Settings such as bounds or decay are not included.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(1.0);
+    ///  let fingerprint = histogram.fingerprint();
+    ///  assert_eq!(histogram.clone().fingerprint(), fingerprint);
+    ///  histogram.add(2.0);
+    ///  assert_ne!(histogram.fingerprint(), fingerprint);
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+        let mut hash = OFFSET;
+        let mut write = |word: u64| {
+            for byte in word.to_le_bytes().iter() {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(PRIME);
+            }
+        };
+        write(self.max_bins as u64);
+        write(self.total);
+        for i in self.bins.iter() {
+            write(i.value.to_bits());
+            write(i.count as u64);
+        }
+        hash
+    }
+
+    /// iter_bins returns an iterator over the bins as `(value, count)` pairs in ascending
+    /// order of value. `&Histogram` implements IntoIterator with the same items, so a
+    /// histogram can also be used directly in a for loop.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(2.0);
+    ///  histogram.add(1.0);
+    ///  histogram.add(2.0);
+    ///  let bins: Vec<(f64, usize)> = histogram.iter_bins().collect();
+    ///  assert_eq!(bins, vec![(1.0, 1), (2.0, 2)]);
+    /// ```
+    pub fn iter_bins(&self) -> Bins<'_> {
+        Bins {
+            inner: self.bins.iter(),
+        }
+    }
+
+    /// effective_sample_size returns `(sum of counts)^2 / sum of counts^2` over the bins (Kish's
+    /// formula with the bin counts as weights). Equal counts give the number of bins, and the
+    /// more the weight concentrates in a few bins, for example after [`Histogram::decay`] or
+    /// heavy [`Histogram::add_weighted`] values, the smaller it gets, down to 1 for a single
+    /// bin. An empty histogram gives 0.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 3);
+    ///  histogram.add_weighted(2.0, 1);
+    ///  assert_eq!(histogram.effective_sample_size(), 1.6);
+    /// ```
+    pub fn effective_sample_size(&self) -> f64 {
+        let (sum, squares) = self.bins.iter().fold((0.0, 0.0), |(sum, squares), i| {
+            let count = i.count as f64;
+            (sum + count, squares + count * count)
+        });
+        if squares == 0.0 {
+            return 0.0;
+        }
+        sum * sum / squares
+    }
+
+    /// fill_imbalance returns the coefficient of variation of the bin counts (their population
+    /// standard deviation divided by their mean), 0 when every bin holds the same count. A
+    /// high value means a few bins hold most of the values while the others hold little, so
+    /// the bins are not spent where the data is, which hints at tuning `max_bins` or the
+    /// merge options. Zero-count anchors are not counted. Returns None when the histogram is
+    /// empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 5);
+    ///  histogram.add_weighted(2.0, 5);
+    ///  assert_eq!(histogram.fill_imbalance(), Some(0.0));
+    /// ```
+    pub fn fill_imbalance(&self) -> Option<f64> {
+        let counts: Vec<f64> = self
+            .bins
+            .iter()
+            .filter(|i| i.count > 0)
+            .map(|i| i.count as f64)
+            .collect();
+        if counts.is_empty() {
+            return None;
+        }
+        let n = counts.len() as f64;
+        let mean = counts.iter().sum::<f64>() / n;
+        let variance = counts.iter().map(|c| (c - mean) * (c - mean)).sum::<f64>() / n;
+        Some(variance.sqrt() / mean)
+    }
+
+    /// top_n_mass returns the fraction of the total held by the `n` bins with the highest
+    /// counts, which tells how concentrated the values are. `n` is clamped to the number of
+    /// bins. Returns None when the histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 8);
+    ///  histogram.add(2.0);
+    ///  histogram.add(3.0);
+    ///  assert_eq!(histogram.top_n_mass(1), Some(0.8));
+    /// ```
+    pub fn top_n_mass(&self, n: usize) -> Option<f64> {
+        if self.total == 0 {
+            return None;
+        }
+        let mut counts: Vec<u64> = self.bins.iter().map(|i| i.count as u64).collect();
+        counts.sort_unstable_by_key(|count| Reverse(*count));
+        let top = counts
+            .iter()
+            .take(n)
+            .fold(0u64, |sum, c| sum.saturating_add(*c));
+        Some(top as f64 / self.total as f64)
+    }
+
+    /// cumulative_counts returns the running total of the counts after each bin in
+    /// ascending order of value, so the last element equals the total.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 2);
+    ///  histogram.add_weighted(2.0, 3);
+    ///  assert_eq!(histogram.cumulative_counts(), vec![2, 5]);
+    /// ```
+    pub fn cumulative_counts(&self) -> Vec<u64> {
+        let mut count: u64 = 0;
+        self.bins
+            .iter()
+            .map(|i| {
+                count = count.saturating_add(i.count as u64);
+                count
+            })
+            .collect()
+    }
+
+    /// top_values returns the most frequent exact values with their estimated counts, highest
+    /// count first. The values are tracked apart from the bins, so heavy hitters stay visible
+    /// even when merging blurs them into their neighbours. Tracking is enabled with
+    /// [`HistogramBuilder::top_k`], otherwise the result is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::builder().max_bins(10).top_k(1).build();
+    ///  for i in 1..=20 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  histogram.add_weighted(2.5, 50);
+    ///  assert_eq!(histogram.top_values()[0].0, 2.5);
+    /// ```
+    pub fn top_values(&self) -> Vec<(f64, usize)> {
+        self.top_k
+            .as_ref()
+            .map(|top_k| top_k.top(top_k.k()))
+            .unwrap_or_default()
+    }
+
+    /// reservoir_quantile returns the exact q-quantile of the raw values kept in the reservoir,
+    /// a uniform random sample of the added values enabled with
+    /// [`HistogramBuilder::reservoir`]. Comparing it to [`Histogram::quantile`] shows the
+    /// binning error on your own data. The reservoir isn't affected by decay or rescaling.
+    /// Returns None without a reservoir or when it is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::builder().max_bins(10).reservoir(1000).build();
+    ///  for i in 1..=1000 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.reservoir_quantile(0.5), Some(500.0));
+    /// ```
+    pub fn reservoir_quantile(&self, q: f64) -> Option<f64> {
+        let mut samples = self.reservoir.as_ref()?.samples().to_vec();
+        let rank = target_rank(q, samples.len() as u64)?;
+        samples.sort_by(|a, b| a.total_cmp(b));
+        match samples.get((rank.max(1) - 1) as usize) {
+            Some(value) => Some(*value),
+            None if q <= MAX_QUANTILE => samples.last().copied(),
+            None => None,
+        }
+    }
+
+    /// freeze ends the collection phase and turns the histogram into a read only
+    /// [`FrozenHistogram`], whose quantile, cdf and rank queries take O(log n) thanks to
+    /// precomputed cumulative counts.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  let p90 = histogram.quantile(0.9);
+    ///  let frozen = histogram.freeze();
+    ///  assert_eq!(frozen.quantile(0.9), p90);
+    /// ```
+    pub fn freeze(self) -> FrozenHistogram {
+        let mut values = Vec::with_capacity(self.bins.len());
+        let mut cumulative = Vec::with_capacity(self.bins.len());
+        let mut count: u64 = 0;
+        // empty anchors would be found by the binary search for rank 0
+        for i in self.bins.iter().filter(|i| i.count > 0) {
+            count = count.saturating_add(i.count as u64);
+            values.push(i.value);
+            cumulative.push(count);
+        }
+        FrozenHistogram {
+            values,
+            cumulative,
+            total: self.total,
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    /// expand reconstructs approximate raw samples for tools which only accept individual
+    /// values: every bin yields its value `count` times, in ascending order. This is lossy,
+    /// the values merged into a bin all come back as the bin value. The iterator is lazy,
+    /// but it yields `total` values, so collecting it into memory is expensive for large totals.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 2);
+    ///  histogram.add(3.0);
+    ///  assert_eq!(histogram.expand().collect::<Vec<f64>>(), vec![1.0, 1.0, 3.0]);
+    /// ```
+    pub fn expand(&self) -> impl Iterator<Item = f64> + '_ {
+        self.bins
+            .iter()
+            .flat_map(|i| std::iter::repeat_n(i.value, i.count))
+    }
+
+    /// bins_in_range returns the bins whose value lies inside `[low, high]` in ascending
+    /// order, for zooming into a sub-range. An empty vector is returned when `low > high`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for i in 1..=5 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.bins_in_range(2.0, 3.5), vec![(2.0, 1), (3.0, 1)]);
+    /// ```
+    pub fn bins_in_range(&self, low: f64, high: f64) -> Vec<(f64, usize)> {
+        self.iter_bins()
+            .skip_while(|(value, _)| *value < low)
+            .take_while(|(value, _)| *value <= high)
+            .collect()
+    }
+
+    /// neighbors returns the bins surrounding `x` as `(value, count)` pairs: the last bin at or
+    /// below `x` and the first bin above it, None on a side without bins. This helps to see
+    /// which bins a quantile near `x` is computed from.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(1.0);
+    ///  histogram.add(3.0);
+    ///  assert_eq!(histogram.neighbors(2.0), (Some((1.0, 1)), Some((3.0, 1))));
+    ///  assert_eq!(histogram.neighbors(0.0), (None, Some((1.0, 1))));
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn neighbors(&self, x: f64) -> (Option<(f64, usize)>, Option<(f64, usize)>) {
+        let mut below = None;
+        for (value, count) in self.iter_bins() {
+            if value > x {
+                return (below, Some((value, count)));
+            }
+            below = Some((value, count));
+        }
+        (below, None)
+    }
+
+    /// bin_at returns the value and count of the bin at the sorted position `index`, or None
+    /// when `index` is out of range. The bins are kept in a linked list, so the lookup walks
+    /// `index` bins, use [`Histogram::iter_bins`] to visit all of them.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(2.0);
+    ///  histogram.add(1.0);
+    ///  assert_eq!(histogram.bin_at(1), Some((2.0, 1)));
+    ///  assert_eq!(histogram.bin_at(2), None);
+    /// ```
+    pub fn bin_at(&self, index: usize) -> Option<(f64, usize)> {
+        self.iter_bins().nth(index)
+    }
+
+    /// modal_interval returns the value range represented by the bin with the highest count,
+    /// from the midpoint with its left neighbour to the midpoint with its right neighbour
+    /// (the minimum and maximum value at the ends). The values of a bin are spread around its
+    /// value, so the interval is a more honest mode than a single point. The first bin wins
+    /// when several bins have the highest count. Returns None when the histogram is empty.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(1.0);
+    ///  histogram.add_weighted(2.0, 5);
+    ///  histogram.add(4.0);
+    ///  assert_eq!(histogram.modal_interval(), Some((1.5, 3.0)));
+    /// ```
+    pub fn modal_interval(&self) -> Option<(f64, f64)> {
+        let mut best: Option<(usize, (f64, f64))> = None;
+        for (i, bounds) in self.bins.iter().zip(self.bin_bounds()) {
+            if i.count > 0 && best.is_none_or(|(count, _)| i.count > count) {
+                best = Some((i.count, bounds));
+            }
+        }
+        best.map(|(_, bounds)| bounds)
+    }
+
+    /// peak_count returns the count of the busiest bin, which is useful for detecting
+    /// load concentrated on a narrow range of values. Returns None when the histogram is empty.
+    pub fn peak_count(&self) -> Option<usize> {
+        self.bins
+            .iter()
+            .map(|i| i.count)
+            .filter(|&count| count > 0)
+            .max()
+    }
+
+    /// num_modes counts the peaks of the distribution, a value above 1 means it is multimodal.
+    /// The heuristic works on the bin counts, with an empty bin assumed beyond both ends:
+    /// each local maximum is a candidate peak, and its prominence is how far the counts
+    /// drop on the way to a higher peak (or to the end) on both sides, taking the smaller
+    /// drop. A candidate counts as a mode when its prominence is at least `prominence` times
+    /// its own count, so `prominence` is between 0 (every local maximum) and 1 (only peaks
+    /// separated by empty space), the highest peak always counts. Bins have different
+    /// widths after merging, so small bumps are noise and values around 0.5 work well.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  for (value, count) in [(1.0, 2), (2.0, 9), (3.0, 2), (4.0, 8), (5.0, 1)].iter() {
+    ///       histogram.add_weighted(*value, *count);
+    ///  }
+    ///  assert_eq!(histogram.num_modes(0.5), 2);
+    ///  assert_eq!(histogram.num_modes(0.9), 1);
+    /// ```
+    pub fn num_modes(&self, prominence: f64) -> usize {
+        let mut counts = vec![0];
+        counts.extend(self.bins.iter().map(|i| i.count).filter(|c| *c > 0));
+        counts.push(0);
+        let mut modes = 0;
+        for index in 1..counts.len() - 1 {
+            let peak = counts[index];
+            if !(peak > counts[index - 1] && peak >= counts[index + 1]) {
+                continue;
+            }
+            let lowest = |range: &mut dyn Iterator<Item = &usize>| {
+                let mut lowest = peak;
+                for &count in range {
+                    if count > peak {
+                        break;
+                    }
+                    lowest = lowest.min(count);
+                }
+                lowest
+            };
+            let left = lowest(&mut counts[..index].iter().rev());
+            let right = lowest(&mut counts[index + 1..].iter());
+            if (peak - left.max(right)) as f64 >= prominence * peak as f64 {
+                modes += 1;
+            }
+        }
+        modes
+    }
+
+    /// smoothed returns the bins with a centered moving average applied on their counts,
+    /// `window` is the number of bins averaged around each bin, near the edges the window
+    /// shrinks to the bins available. The histogram itself is not changed.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add((i % 7) as f64);
+    ///  }
+    ///  let smoothed = histogram.smoothed(3);
+    ///  assert_eq!(smoothed.len(), 7);
+    /// ```
+    pub fn smoothed(&self, window: usize) -> Vec<(f64, f64)> {
+        let bins: Vec<(f64, f64)> = self
+            .bins
+            .iter()
+            .map(|i| (i.value, i.count as f64))
+            .collect();
+        let half = window / 2;
+        let mut result = Vec::with_capacity(bins.len());
+        for (index, (value, _)) in bins.iter().enumerate() {
+            let start = index.saturating_sub(half);
+            let end = (index + half + 1).min(bins.len());
+            let sum: f64 = bins[start..end].iter().map(|(_, count)| count).sum();
+            result.push((*value, sum / (end - start) as f64));
+        }
+        result
+    }
+
+    /// compact merges bins until the number of bins fits into `max_bins`. Bins are merged
+    /// automatically after each insert, so this is only needed to force compaction, for
+    /// example after changing the configuration, before serialization, or to drop the extra
+    /// bins allowed by [`HistogramBuilder::merge_slack`].
+    pub fn compact(&mut self) {
+        self.merge_down_to(self.max_bins);
+    }
+
+    /// shrink_to merges the bins down to at most `target_bins` (at least 1) to release memory
+    /// under pressure, while the configured `max_bins` stays unchanged: new values grow the
+    /// bins again up to `max_bins`, so the resolution recovers for future data. The merged
+    /// bins themselves keep the coarser resolution. A target above `max_bins` only compacts
+    /// like [`Histogram::compact`].
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=100 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  histogram.shrink_to(5);
+    ///  assert_eq!(histogram.iter_bins().count(), 5);
+    ///  for i in 101..=200 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  assert_eq!(histogram.iter_bins().count(), 20);
+    /// ```
+    pub fn shrink_to(&mut self, target_bins: usize) {
+        self.merge_down_to(target_bins.min(self.max_bins));
+    }
+
+    fn merge_bin(&mut self) {
+        if self.bins.len() > self.max_bins.saturating_add(self.merge_slack) {
+            self.merge_down_to(self.max_bins);
+        }
+    }
+
+    // merge_down_to merges bins until there are at most `limit` of them. When more
+    // than one merge is needed the bins are merged with reduce_bins, which avoids
+    // scanning the whole list for every merge.
+    fn merge_down_to(&mut self, limit: usize) {
+        let limit = limit.max(1);
+        let merges = self.merges;
+        while self.bins.len() > limit && self.drop_anchor() {}
+        if self.bins.len() > limit + 1 {
+            let tail = self.tail_limit();
+            let bins: Vec<Bin> = std::mem::replace(&mut self.bins, LinkedList::new())
+                .into_iter()
+                .collect();
+            let (bins, merges) = reduce_bins(
+                bins,
+                limit,
+                tail,
+                self.saturation_hook,
+                self.singleton_tolerance,
+            );
+            self.bins = bins.into_iter().collect();
+            self.merges += merges;
+        }
+        while self.bins.len() > limit {
+            self.merge_closest();
+        }
+        if self.singleton_tolerance.is_some() && self.merges != merges {
+            // absorbed single values moved to their neighbour
+            self.refresh_moments();
+        }
+    }
+
+    // refresh_moments recomputes the running moments from the bins.
+    fn refresh_moments(&mut self) {
+        self.moments = Moments::of(self.bins.iter());
+    }
+
+    // drop_anchor removes the empty anchor closest to its neighbours, which is the one
+    // made redundant by real values landing next to it. Returns false when there are
+    // no anchors left.
+    fn drop_anchor(&mut self) -> bool {
+        if !self.anchored {
+            return false;
+        }
+        let values: Vec<(f64, usize)> = self.iter_bins().collect();
+        let mut closest: Option<(f64, usize)> = None;
+        for (index, (value, count)) in values.iter().enumerate() {
+            if *count > 0 {
+                continue;
+            }
+            let left = index
+                .checked_sub(1)
+                .map_or(f64::INFINITY, |l| value - values[l].0);
+            let right = values.get(index + 1).map_or(f64::INFINITY, |r| r.0 - value);
+            if closest.is_none_or(|(gap, _)| left.min(right) < gap) {
+                closest = Some((left.min(right), index));
+            }
+        }
+        match closest {
+            Some((_, index)) => {
+                self.bins.remove(index);
+                true
+            }
+            None => {
+                self.anchored = false;
+                false
+            }
+        }
+    }
+
+    // merge_closest combines the two adjacent bins with the smallest gap, see closest_pair.
+    fn merge_closest(&mut self) {
+        let mut min_delta_index = match self.tail_limit() {
+            Some((tail, limit)) => closest_pair(self.bins.iter(), |index, count| {
+                !(tail[index - 1] || tail[index]) || count <= limit
+            }),
+            None => 0,
+        };
+        if min_delta_index == 0 {
+            min_delta_index = closest_pair(self.bins.iter(), |_, _| true);
+        }
+        // must have a current and last bin
+        let current_bin = self
+            .bins
+            .remove(min_delta_index)
+            .expect("retrieve current bin fail");
+        let last_bin = self
+            .bins
+            .remove(min_delta_index - 1)
+            .expect("retrieve last bin fail");
+        self.bins.insert(
+            min_delta_index - 1,
+            merge_pair(
+                last_bin,
+                current_bin,
+                self.saturation_hook,
+                self.singleton_tolerance,
+            ),
+        );
+        self.merges += 1;
+    }
+
+    // tail_limit returns which bins lie in the bottom or top decile of the counts
+    // and the largest count a merged bin may have there, so each tail keeps at least
+    // `tail_bins` bins. None when tail reservation is off.
+    fn tail_limit(&self) -> Option<(Vec<bool>, usize)> {
+        if self.tail_bins == 0 {
+            return None;
+        }
+        let decile = self.total as f64 / 10.0;
+        let mut seen = 0.0;
+        let mut tail: Vec<bool> = Vec::with_capacity(self.bins.len());
+        for i in self.bins.iter() {
+            let after = seen + i.count as f64;
+            tail.push(seen < decile || after > self.total as f64 - decile);
+            seen = after;
+        }
+        Some((tail, (decile / self.tail_bins as f64).ceil() as usize))
+    }
+
+    /// sparkline renders the bin counts as a one line sparkline made of the block characters
+    /// `▁▂▃▄▅▆▇█`, for embedding the shape in logs or chat. Each bin is one character, when
+    /// there are more than 32 bins adjacent bins are grouped so the line is at most 32
+    /// characters wide. An empty histogram gives an empty string.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 1);
+    ///  histogram.add_weighted(2.0, 8);
+    ///  histogram.add_weighted(3.0, 4);
+    ///  assert_eq!(histogram.sparkline(), "▁█▄");
+    /// ```
+    pub fn sparkline(&self) -> String {
+        const GLYPHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        const WIDTH: usize = 32;
+        let counts: Vec<u64> = self.bins.iter().map(|i| i.count as u64).collect();
+        let group = counts.len().div_ceil(WIDTH).max(1);
+        let columns: Vec<u64> = counts.chunks(group).map(|c| c.iter().sum()).collect();
+        let peak = columns.iter().copied().max().unwrap_or(0).max(1);
+        columns
+            .iter()
+            .map(|&c| GLYPHS[((c as f64 / peak as f64 * 8.0).ceil() as usize).saturating_sub(1)])
+            .collect()
+    }
+
+    /// render_top renders like `to_string` but only the `n` bins with the highest counts, in
+    /// ascending order of value, followed by a `... N more bins` line when bins were left
+    /// out. This keeps the output of histograms with a large `max_bins` readable in logs.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 1);
+    ///  histogram.add_weighted(2.0, 3);
+    ///  histogram.add_weighted(3.0, 2);
+    ///  let rendered = histogram.render_top(2);
+    ///  assert!(rendered.starts_with("Total: 6\n2."));
+    ///  assert!(rendered.ends_with("... 1 more bins\n"));
+    /// ```
+    pub fn render_top(&self, n: usize) -> String {
+        let mut top: Vec<(usize, &Bin)> = self.bins.iter().enumerate().collect();
+        top.sort_by_key(|(index, i)| (Reverse(i.count), *index));
+        top.truncate(n);
+        top.sort_by_key(|(index, _)| *index);
+        let mut result = format!("Total: {}\n", self.total);
+        for (_, i) in top.iter() {
+            result += &self.bar(i);
+            result.push('\n');
+        }
+        let hidden = self.bins.len() - top.len();
+        if hidden > 0 {
+            result += &format!("... {} more bins\n", hidden);
+        }
+        result
+    }
+
+    /// render_normalized renders each bin as its value, a space and a bar of dots scaled to the
+    /// fullest bin, `count / peak_count * max_height` rounded, so the tallest bar is always
+    /// `max_height` long. Unlike `to_string`, which scales by the total, histograms of very
+    /// different sizes look alike when their shapes are alike.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add_weighted(1.0, 2);
+    ///  histogram.add_weighted(2.0, 4);
+    ///  assert_eq!(histogram.render_normalized(4), "1 ..\n2 ....\n");
+    /// ```
+    pub fn render_normalized(&self, max_height: usize) -> String {
+        let peak = self.bins.iter().map(|i| i.count).max().unwrap_or(0).max(1);
+        let mut result = String::new();
+        for i in self.bins.iter() {
+            let height = (i.count as f64 / peak as f64 * max_height as f64).round() as usize;
+            result += &format!("{} {}\n", i.value, ".".repeat(height));
+        }
+        result
+    }
+
+    // bar renders one bin as its value followed by dots for its share of the total.
+    fn bar(&self, bin: &Bin) -> String {
+        let mut bar = format!("{}", bin.value);
+        let size = (bin.count as f64 / self.total as f64 * 100.0) as usize;
+        for _i in 1..size {
+            bar += ".";
+        }
+        bar
+    }
+
+    /// percentile_table renders the count, mean, stddev and the common percentiles
+    /// (p50, p75, p90, p95, p99 and p99.9) as a table with aligned columns, which is a richer
+    /// alternative to the bars printed by `to_string`. Missing values are printed as `-`.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(20);
+    ///  for i in 1..=1000 {
+    ///       histogram.add(i as f64);
+    ///  }
+    ///  println!("{}", histogram.percentile_table());
+    /// ```
+    pub fn percentile_table(&self) -> String {
+        const PERCENTILES: [(&str, f64); 6] = [
+            ("p50", 0.5),
+            ("p75", 0.75),
+            ("p90", 0.9),
+            ("p95", 0.95),
+            ("p99", 0.99),
+            ("p99.9", 0.999),
+        ];
+        let cell = |value: Option<f64>| match value {
+            Some(v) => format!("{:.3}", v),
+            None => String::from("-"),
+        };
+        let mut result = format!("{:<8}{:>16}\n", "count", self.total);
+        result += &format!("{:<8}{:>16}\n", "mean", cell(self.mean()));
+        result += &format!("{:<8}{:>16}\n", "stddev", cell(self.stddev()));
+        for (label, q) in PERCENTILES.iter() {
+            result += &format!("{:<8}{:>16}\n", label, cell(self.quantile(*q)));
+        }
+        result
+    }
+
+    /// return a report from current histogram
+    /// # Example
+    /// ```
+    /// use crate::stream_histogram::Histogram;
+    /// let mut histogram = Histogram::new(10);
+    /// for i in 1..=100 {
+    ///     histogram.add(i as f64);
+    /// }
+    /// if let Some(report) = histogram.report(){
+    ///    println!("{:?}", report);
+    /// }
+    /// ```
+    pub fn report(&self) -> Option<HistogramReport> {
+        HistogramReport::new(self)
+    }
+
+    /// status tells whether the statistics of the histogram are meaningful, so reporting
+    /// code can skip computing a variance or quantiles of an empty or constant histogram.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::{Histogram, HistogramStatus};
+    ///  let mut histogram = Histogram::new(10);
+    ///  assert_eq!(histogram.status(), HistogramStatus::Empty);
+    ///  histogram.add(1.0);
+    ///  assert_eq!(histogram.status(), HistogramStatus::SingleValue);
+    ///  histogram.add(2.0);
+    ///  assert_eq!(histogram.status(), HistogramStatus::Ready);
+    /// ```
+    pub fn status(&self) -> HistogramStatus {
+        match (self.total, self.min, self.max) {
+            (0, _, _) => HistogramStatus::Empty,
+            (1, _, _) => HistogramStatus::SingleValue,
+            (_, Some(min), Some(max)) if min == max => HistogramStatus::Degenerate,
+            _ => HistogramStatus::Ready,
+        }
+    }
+
+    /// replay rebuilds a histogram from a recorded sequence of values, adding them in chunks of
+    /// `chunk` values (at least 1) and taking a report after each chunk, so the report list
+    /// shows where a statistic starts to diverge. Replays of the same values are identical.
+    /// Chunks which leave the histogram empty, e.g. only NaN, produce no report.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let values: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+    ///  let reports = Histogram::replay(&values, 20, 30);
+    ///  assert_eq!(reports.len(), 4);
+    ///  assert_eq!(reports[0].total, 30);
+    ///  assert_eq!(reports[3].total, 100);
+    /// ```
+    pub fn replay(values: &[f64], max_bins: usize, chunk: usize) -> Vec<HistogramReport> {
+        let mut histogram = Histogram::new(max_bins);
+        values
+            .chunks(chunk.max(1))
+            .filter_map(|values| {
+                for v in values {
+                    histogram.add(*v);
+                }
+                histogram.report()
+            })
+            .collect()
+    }
+
+    /// quick_stats returns the count, mean, min, max and variance computed in a single pass
+    /// over the bins without allocating, a lighter alternative to [`Histogram::report`]
+    /// which also walks the bins for the quantiles. The mean and variance are accumulated
+    /// with Welford's method, so they may differ from [`Histogram::mean`] and
+    /// [`Histogram::variance`] in the last bits.
+    /// # Examples
+    ///
+    /// ```
+    ///  use crate::stream_histogram::Histogram;
+    ///  let mut histogram = Histogram::new(10);
+    ///  histogram.add(1.0);
+    ///  histogram.add(3.0);
+    ///  let stats = histogram.quick_stats();
+    ///  assert_eq!(stats.count, 2);
+    ///  assert_eq!(stats.mean, Some(2.0));
+    ///  assert_eq!(stats.variance, Some(1.0));
+    /// ```
+    pub fn quick_stats(&self) -> QuickStats {
+        let mut count = 0.0;
+        let mut mean = 0.0;
+        let mut m2 = 0.0;
+        for i in self.bins.iter() {
+            if i.count == 0 {
+                continue;
+            }
+            let weight = i.count as f64;
+            count += weight;
+            let delta = i.value - mean;
+            mean += delta * weight / count;
+            m2 += delta * weight * (i.value - mean) + i.m2;
+        }
+        let ready = self.total > 0;
+        QuickStats {
+            count: self.total,
+            mean: Some(mean).filter(|_| ready),
+            min: self.min,
+            max: self.max,
+            variance: Some(m2 / count).filter(|_| ready),
+        }
+    }
+
+    /// try_report is like [`Histogram::report`] but returns [`HistogramError::Empty`]
+    /// instead of None when there is nothing to report.
+    pub fn try_report(&self) -> Result<HistogramReport, HistogramError> {
+        self.report().ok_or(HistogramError::Empty)
+    }
+}
+// MAX_QUANTILE is the largest q accepted by the quantile queries, q slightly above 1
+// comes from accumulated float error and returns the largest value.
+const MAX_QUANTILE: f64 = 1.0 + 1e-9;
+
+// target_rank returns the cumulative count the q-quantile of `total` values has to
+// reach, when `q * total` is an integer except for float rounding error (0.07 * 100
+// is 7.000000000000001) it is snapped to that integer, so boundary quantiles don't
+// skip to the next bin.
+//
+// `total as f64` loses precision above 2^53, so the product is computed exactly in
+// integers: q is split into its mantissa and exponent, `mantissa * total` fits into
+// a u128, and only the fractional part of the rank is handled as a float.
+fn target_rank(q: f64, total: u64) -> Option<u64> {
+    if q.is_nan() {
+        return None;
+    }
+    if q <= 0.0 {
+        return Some(0);
+    }
+    if q.is_infinite() {
+        return Some(u64::MAX);
+    }
+    let bits = q.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & ((1 << 52) - 1);
+    let (mantissa, exponent) = if exponent == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | (1 << 52), exponent - 1075)
+    };
+    let product = mantissa as u128 * total as u128;
+    if exponent >= 0 {
+        let rank = product.checked_shl(exponent as u32).unwrap_or(u128::MAX);
+        return Some(rank.min(u64::MAX as u128) as u64);
+    }
+    let shift = (-exponent) as u32;
+    let (floor, remainder) = if shift >= 128 {
+        (0, product)
+    } else {
+        (product >> shift, product & ((1 << shift) - 1))
+    };
+    let fraction = remainder as f64 / (-exponent as f64).exp2();
+    let (nearest, distance) = if fraction <= 0.5 {
+        (floor, fraction)
+    } else {
+        (floor + 1, 1.0 - fraction)
+    };
+    let rank = if remainder == 0 {
+        floor
+    } else if nearest > 0 && distance <= nearest as f64 * 4.0 * f64::EPSILON {
+        nearest
+    } else {
+        floor + 1
+    };
+    Some(rank.min(u64::MAX as u128) as u64)
+}
+
+// saturating_count adds two bin counts, the result saturates at `usize::MAX`
+// and the hook is invoked with the bin value when that happens.
+fn saturating_count(count: usize, other: usize, value: f64, hook: Option<fn(f64)>) -> usize {
+    match count.checked_add(other) {
+        Some(v) => v,
+        None => {
+            if let Some(hook) = hook {
+                hook(value);
+            }
+            usize::MAX
+        }
+    }
+}
+
+/// Bins is the iterator returned by [`Histogram::iter_bins`], yielding `(value, count)`
+/// pairs in ascending order of value.
+pub struct Bins<'a> {
+    inner: linked_list::Iter<'a, Bin>,
+}
+
+impl<'a> Iterator for Bins<'a> {
+    type Item = (f64, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|i| (i.value, i.count))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Bins<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|i| (i.value, i.count))
+    }
+}
+
+impl<'a> ExactSizeIterator for Bins<'a> {}
+
+impl<'a> IntoIterator for &'a Histogram {
+    type Item = (f64, usize);
+    type IntoIter = Bins<'a>;
+
+    fn into_iter(self) -> Bins<'a> {
+        self.iter_bins()
+    }
+}
+
+// closest_pair returns the index of the second bin of the adjacent pair with the
+// smallest gap, only pairs `allowed` by their second index and their combined
+// count are considered, 0 is returned when there is none. When several gaps are
+// equal the pair with the lower combined count wins, and if the counts are also
+// equal the pair with the lower values wins, so the same input always yields the
+// same bins.
+fn closest_pair<'a>(
+    bins: impl Iterator<Item = &'a Bin>,
+    allowed: impl Fn(usize, usize) -> bool,
+) -> usize {
+    let mut min_delta = f64::INFINITY;
+    let mut min_delta_count = usize::MAX;
+    let mut min_delta_index = 0;
+    let mut last_bin: Option<&Bin> = None;
+    for (index, i) in bins.enumerate() {
+        if let Some(last) = last_bin {
+            let delta = i.value - last.value;
+            let count = i.count.saturating_add(last.count);
+            if !allowed(index, count) {
+                last_bin = Some(i);
+                continue;
+            }
+            if min_delta_index == 0
+                || delta < min_delta
+                || (delta == min_delta && count < min_delta_count)
+            {
+                min_delta = delta;
+                min_delta_count = count;
+                min_delta_index = index;
+            }
+        }
+        last_bin = Some(i);
+    }
+    min_delta_index
+}
+
+// Gap is the distance between two adjacent bins in reduce_bins, ordered like the
+// choice of closest_pair: by delta, then by combined count, then by position.
+struct Gap {
+    delta: f64,
+    count: usize,
+    left: usize,
+    right: usize,
+    versions: (u64, u64),
+}
+
+impl Gap {
+    fn new(bins: &[Bin], left: usize, right: usize, versions: (u64, u64)) -> Gap {
+        Gap {
+            delta: bins[right].value - bins[left].value,
+            count: bins[right].count.saturating_add(bins[left].count),
+            left,
+            right,
+            versions,
+        }
+    }
+}
+
+impl PartialEq for Gap {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Gap {}
+
+impl PartialOrd for Gap {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Gap {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.delta
+            .total_cmp(&other.delta)
+            .then(self.count.cmp(&other.count))
+            .then(self.left.cmp(&other.left))
+    }
+}
+
+// reduce_bins merges the sorted bins until at most `limit` are left and returns them
+// with the number of merges. It merges the same pairs as calling closest_pair once per
+// merge, but keeps the gaps in a heap, so n merges cost O(n log n) instead of O(n^2).
+// With a tail limit from Histogram::tail_limit, gaps which would merge a tail bin beyond
+// the limit are set aside and only taken, closest first, when no other gap is left. A
+// merged bin is in a tail when either of its bins was.
+fn reduce_bins(
+    mut bins: Vec<Bin>,
+    limit: usize,
+    tail_limit: Option<(Vec<bool>, usize)>,
+    hook: Option<fn(f64)>,
+    singleton_tolerance: Option<f64>,
+) -> (Vec<Bin>, u64) {
+    let n = bins.len();
+    let mut prev: Vec<Option<usize>> = (0..n).map(|i| i.checked_sub(1)).collect();
+    let mut next: Vec<Option<usize>> = (0..n).map(|i| Some(i + 1).filter(|&j| j < n)).collect();
+    let mut alive = vec![true; n];
+    let mut versions = vec![0u64; n];
+    let mut gaps: BinaryHeap<Reverse<Gap>> = (1..n)
+        .map(|i| Reverse(Gap::new(&bins, i - 1, i, (0, 0))))
+        .collect();
+    let (mut tail, tail_count) = tail_limit.unwrap_or_else(|| (vec![false; n], usize::MAX));
+    let mut blocked: Vec<Gap> = Vec::new();
+    let mut len = n;
+    let mut merges = 0;
+    while len > limit {
+        let gap = match gaps.pop() {
+            Some(Reverse(gap)) => {
+                let (left, right) = (gap.left, gap.right);
+                if !alive[left]
+                    || !alive[right]
+                    || gap.versions != (versions[left], versions[right])
+                {
+                    continue;
+                }
+                if (tail[left] || tail[right]) && gap.count > tail_count {
+                    blocked.push(gap);
+                    continue;
+                }
+                gap
+            }
+            None => {
+                blocked.retain(|gap| {
+                    alive[gap.left]
+                        && alive[gap.right]
+                        && gap.versions == (versions[gap.left], versions[gap.right])
+                });
+                match (0..blocked.len()).min_by(|&a, &b| blocked[a].cmp(&blocked[b])) {
+                    Some(index) => blocked.swap_remove(index),
+                    None => break,
+                }
+            }
+        };
+        let (left, right) = (gap.left, gap.right);
+        let last_bin = std::mem::replace(&mut bins[left], Bin::new(0.0, 0));
+        let current_bin = std::mem::replace(&mut bins[right], Bin::new(0.0, 0));
+        bins[left] = merge_pair(last_bin, current_bin, hook, singleton_tolerance);
+        alive[right] = false;
+        tail[left] |= tail[right];
+        versions[left] += 1;
+        next[left] = next[right];
+        if let Some(after) = next[right] {
+            prev[after] = Some(left);
+            gaps.push(Reverse(Gap::new(
+                &bins,
+                left,
+                after,
+                (versions[left], versions[after]),
+            )));
+        }
+        if let Some(before) = prev[left] {
+            gaps.push(Reverse(Gap::new(
+                &bins,
+                before,
+                left,
+                (versions[before], versions[left]),
+            )));
+        }
+        len -= 1;
+        merges += 1;
+    }
+    let bins = bins
+        .into_iter()
+        .zip(alive)
+        .filter(|(_, alive)| *alive)
+        .map(|(bin, _)| bin)
+        .collect();
+    (bins, merges)
+}
+
+// weighted_mean returns the mean of the bin values weighted by the given part of their
+// count, None when there is no weight.
+fn weighted_mean(bins: &[(&Bin, f64)]) -> Option<f64> {
+    let (weight, sum) = bins.iter().fold((0.0, 0.0), |(w, s), (i, weight)| {
+        (w + weight, s + weight * i.value)
+    });
+    if weight > 0.0 {
+        Some(sum / weight)
+    } else {
+        None
+    }
+}
+
+// merge_pair merges two adjacent bins with combine_bins, except that a bin holding a
+// single value within `singleton_tolerance` of a larger neighbour is added to that
+// neighbour at its value, see HistogramBuilder::preserve_singletons.
+fn merge_pair(
+    last_bin: Bin,
+    current_bin: Bin,
+    hook: Option<fn(f64)>,
+    singleton_tolerance: Option<f64>,
+) -> Bin {
+    let delta = current_bin.value - last_bin.value;
+    let absorb = singleton_tolerance.is_some_and(|tolerance| delta <= tolerance)
+        && (last_bin.count == 1) != (current_bin.count == 1);
+    if !absorb {
+        return combine_bins(last_bin, current_bin, hook);
+    }
+    let (kept, single) = if last_bin.count == 1 {
+        (current_bin, last_bin)
+    } else {
+        (last_bin, current_bin)
+    };
+    Bin {
+        value: kept.value,
+        count: saturating_count(kept.count, single.count, kept.value, hook),
+        m2: kept.m2 + single.m2 + delta * delta,
+    }
+}
+
+// combine_bins merges two adjacent bins into one bin at their weighted average,
+// the spread of both bins and the distance between them are kept in m2. The
+// average is kept between both values, so float rounding or an overflow of the
+// weighted sum can't move the bin past its neighbours.
+fn combine_bins(last_bin: Bin, current_bin: Bin, hook: Option<fn(f64)>) -> Bin {
+    let total_count = saturating_count(current_bin.count, last_bin.count, current_bin.value, hook);
+    let mut value = (current_bin.value * current_bin.count as f64
+        + last_bin.value * last_bin.count as f64)
+        / total_count as f64;
+    if value.is_nan() {
+        // infinite sums of opposite signs, keep the heavier bin
+        value = if last_bin.count >= current_bin.count {
+            last_bin.value
+        } else {
+            current_bin.value
+        };
+    }
+    let value = value.max(last_bin.value).min(current_bin.value);
+    let delta = current_bin.value - last_bin.value;
+    let spread = if total_count == 0 {
+        0.0
+    } else {
+        delta * delta * (last_bin.count as f64 * current_bin.count as f64) / total_count as f64
+    };
+    let m2 = last_bin.m2 + current_bin.m2 + spread;
+    Bin {
+        value,
+        count: total_count,
+        m2,
+    }
+}
+
+// push_sorted appends a bin to bins sorted by value, adding its count to the
+// last bin when both hold the same value.
+fn push_sorted(bins: &mut Vec<Bin>, bin: Bin, hook: Option<fn(f64)>) {
+    match bins.last_mut() {
+        Some(last) if last.value == bin.value => {
+            last.count = saturating_count(last.count, bin.count, bin.value, hook);
+            last.m2 += bin.m2;
+        }
+        _ => bins.push(bin),
+    }
+}
+
+// BinSummary print a compact view of the bins, only the first and last
+// few bins are listed when there are too many of them.
+struct BinSummary<'a>(&'a LinkedList<Bin>);
+
+impl<'a> fmt::Debug for BinSummary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const HEAD: usize = 3;
+        const TAIL: usize = 2;
+        let len = self.0.len();
+        write!(f, "({}) [", len)?;
+        for (index, i) in self.0.iter().enumerate() {
+            if len > HEAD + TAIL && index >= HEAD && index < len - TAIL {
+                if index == HEAD {
+                    write!(f, ", ..")?;
+                }
+                continue;
+            }
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", i.value, i.count)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Debug print `max_bins`, `total`, `min`, `max` and a compact summary of the bins
+/// (the number of bins and the `value: count` of the first and last few bins),
+/// which keeps `dbg!(&histogram)` readable. The alternate form `{:#?}` prints every
+/// field and all the bins.
+/// # Examples
+///
+/// ```
+///  use crate::stream_histogram::Histogram;
+///  let mut histogram = Histogram::new(10);
+///  for i in 1..=100 {
+///       histogram.add(i as f64);
+///  }
+///  println!("{:?}", histogram);
+///  println!("{:#?}", histogram);
+/// ```
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            f.debug_struct("Histogram")
+                .field("max_bins", &self.max_bins)
+                .field("total", &self.total)
+                .field("min", &self.min)
+                .field("max", &self.max)
+                .field("bounds", &self.bounds)
+                .field("clamped", &self.clamped)
+                .field("merges", &self.merges)
+                .field("bins", &self.bins)
+                .finish()
+        } else {
+            f.debug_struct("Histogram")
+                .field("max_bins", &self.max_bins)
+                .field("total", &self.total)
+                .field("min", &self.min)
+                .field("max", &self.max)
+                .field("bins", &BinSummary(&self.bins))
+                .finish()
+        }
+    }
+}
+
+/// Summary holds the statistics computed by [`Histogram::summarize`] in one pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub count: u64,
+    pub mean: Option<f64>,
+    pub variance: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// the requested quantiles in the same order, None when the histogram is empty or q is invalid
+    pub quantiles: Vec<Option<f64>>,
+}
+
+/// SummaryDto is a compact snapshot of a histogram without any bins, created by
+/// [`Histogram::summary_dto`] for shipping summaries over the wire. With the `serde` feature
+/// it implements `Serialize` and `Deserialize`, the statistics of an empty histogram are
+/// left out.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SummaryDto {
+    pub count: u64,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub mean: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub min: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub max: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub variance: Option<f64>,
+    /// `(q, value)` pairs of the requested quantiles, empty when the histogram is empty
+    pub percentiles: Vec<(f64, f64)>,
+}
+
+/// Display (and so the `to_string` method) print the result in a simple visulazation style,
+/// each line print the bucket and the size in a dot mode.
+/// # Examples
+///
+/// ```
+///  use rand::distributions::{Normal, Distribution};
+///  use crate::stream_histogram::Histogram;
+///  let normal = Normal::new(10.0, 10.0);
+///  let mut histogram = Histogram::new(20);
+///  for _i in 1..=100000 {
+///       let v = normal.sample(&mut rand::thread_rng());
+///       histogram.add(v as f64);
+///  }
+///  println!("{}", histogram.to_string());
+/// ```
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Total: {}", self.total)?;
+        for i in self.bins.iter() {
+            writeln!(f, "{}", self.bar(i))?;
+        }
+        Ok(())
+    }
+}
+
+/// Histograms can be combined with `+`, which delegates to [`Histogram::merge`].
+/// The `max_bins` of the left operand governs the result.
+/// # Examples
+///
+/// ```
+///  use crate::stream_histogram::Histogram;
+///  let mut first = Histogram::new(20);
+///  let mut second = Histogram::new(10);
+///  for i in 1..=100 {
+///       first.add(i as f64);
+///       second.add(i as f64);
+///  }
+///  let combined = first + second;
+///  assert_eq!(combined.report().unwrap().total, 200);
+/// ```
+impl ops::Add for Histogram {
+    type Output = Histogram;
+
+    fn add(mut self, other: Histogram) -> Histogram {
+        self.merge(&other);
+        self
+    }
+}
+
+impl ops::AddAssign for Histogram {
+    fn add_assign(&mut self, other: Histogram) {
+        self.merge(&other);
+    }
+}
+
+/// NanPolicy selects what happens to NaN values, which can't be ordered among the bins, see
+/// [`HistogramBuilder::nan_policy`]. [`Histogram::add`] and [`Histogram::add_weighted`] have no
+/// error to return, so they leave NaN out under every policy and only [`NanPolicy::Skip`]
+/// changes them, by counting it. [`NanPolicy::TreatAsError`] is only surfaced by the checked
+/// [`Histogram::try_add`] and [`Histogram::try_add_weighted`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NanPolicy {
+    /// NaN is silently ignored, `try_add` returns Ok, the default
+    #[default]
+    Reject,
+    /// NaN is ignored but counted, see [`Histogram::nan_count`], `try_add` returns Ok
+    Skip,
+    /// NaN is ignored by `add` like under `Reject`, only `try_add` returns
+    /// [`HistogramError::InvalidValue`]
+    TreatAsError,
+}
+
+/// HistogramStatus is returned by [`Histogram::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistogramStatus {
+    /// no values have been recorded
+    Empty,
+    /// exactly one value has been recorded
+    SingleValue,
+    /// several values have been recorded but they are all equal, so the variance is 0
+    /// and every quantile is the same value
+    Degenerate,
+    /// the values differ, all statistics are meaningful
+    Ready,
+}
+
+/// QuickStats holds the cheap statistics returned by [`Histogram::quick_stats`],
+/// the optional fields are None when the histogram is empty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuickStats {
+    pub count: u64,
+    pub mean: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub variance: Option<f64>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct HistogramReport {
+    pub total: u64,
+    pub mean: f64,
+    pub max: f64,
+    pub min: f64,
+    pub percent99: f64,
+    pub percent90: f64,
+    pub percent50: f64,
+    /// stddev / mean, None when the mean is zero or near zero
+    pub coefficient_of_variation: Option<f64>,
+}
+
+#[allow(dead_code)]
+impl HistogramReport {
+    pub fn new(histogram: &Histogram) -> Option<Self> {
+        Some(HistogramReport {
+            total: histogram.total,
+            mean: histogram.mean()?,
+            max: histogram.max?,
+            min: histogram.min?,
+            percent99: histogram.quantile(0.99)?,
+            percent90: histogram.quantile(0.90)?,
+            percent50: histogram.quantile(0.50)?,
+            coefficient_of_variation: histogram.coefficient_of_variation(),
+        })
+    }
+
+    /// approx_eq compares every numeric field of two reports within the relative tolerance
+    /// `rel_tol`, which makes report based regression tests robust against tiny float differences.
+    /// # Examples
+    ///
+    /// ```
+    /// use crate::stream_histogram::Histogram;
+    /// let mut first = Histogram::new(10);
+    /// let mut second = Histogram::new(10);
+    /// for i in 1..=100 {
+    ///     first.add(i as f64);
+    ///     second.add(i as f64 + 1e-9);
+    /// }
+    /// let (first, second) = (first.report().unwrap(), second.report().unwrap());
+    /// assert!(first.approx_eq(&second, 1e-6));
+    /// ```
+    pub fn approx_eq(&self, other: &HistogramReport, rel_tol: f64) -> bool {
+        let cv = match (
+            self.coefficient_of_variation,
+            other.coefficient_of_variation,
+        ) {
+            (Some(a), Some(b)) => approx_eq(a, b, rel_tol),
+            (None, None) => true,
+            _ => false,
+        };
+        cv && approx_eq(self.total as f64, other.total as f64, rel_tol)
+            && approx_eq(self.mean, other.mean, rel_tol)
+            && approx_eq(self.max, other.max, rel_tol)
+            && approx_eq(self.min, other.min, rel_tol)
+            && approx_eq(self.percent99, other.percent99, rel_tol)
+            && approx_eq(self.percent90, other.percent90, rel_tol)
+            && approx_eq(self.percent50, other.percent50, rel_tol)
+    }
+
+    /// as_fractions returns the values held by the report as `(quantile, value)` pairs in
+    /// ascending order, the minimum and maximum as the 0 and 1 quantiles.
+    /// # Examples
+    ///
+    /// ```
+    /// use crate::stream_histogram::Histogram;
+    /// let mut histogram = Histogram::new(100);
+    /// for i in 1..=100 {
+    ///     histogram.add(i as f64);
+    /// }
+    /// let fractions = histogram.report().unwrap().as_fractions();
+    /// assert_eq!(fractions[1], (0.5, 50.0));
+    /// ```
+    pub fn as_fractions(&self) -> Vec<(f64, f64)> {
+        vec![
+            (0.0, self.min),
+            (0.5, self.percent50),
+            (0.9, self.percent90),
+            (0.99, self.percent99),
+            (1.0, self.max),
+        ]
+    }
+
+    /// drift_score returns a single number describing how much this report moved away from
+    /// `previous`, which can be used as an alert threshold between two time windows.
+    /// For each of mean, percent50, percent90 and percent99 the relative change
+    /// `|current - previous| / max(|current|, |previous|)` is computed (0 when both are 0),
+    /// and the score is the root mean square of those four changes with equal weights.
+    /// An unchanged report scores 0, and the score never exceeds 2.
+    /// # Examples
+    ///
+    /// ```
+    /// use crate::stream_histogram::Histogram;
+    /// let mut before = Histogram::new(10);
+    /// let mut after = Histogram::new(10);
+    /// for i in 1..=100 {
+    ///     before.add(i as f64);
+    ///     after.add(i as f64 * 2.0);
+    /// }
+    /// let (before, after) = (before.report().unwrap(), after.report().unwrap());
+    /// assert!((after.drift_score(&before) - 0.5).abs() < 1e-9);
+    /// ```
+    pub fn drift_score(&self, previous: &HistogramReport) -> f64 {
+        let deltas = [
+            relative_change(self.mean, previous.mean),
+            relative_change(self.percent50, previous.percent50),
+            relative_change(self.percent90, previous.percent90),
+            relative_change(self.percent99, previous.percent99),
+        ];
+        (deltas.iter().map(|d| d * d).sum::<f64>() / deltas.len() as f64).sqrt()
+    }
+
+    /// csv_header returns the CSV header matching the columns of [`HistogramReport::to_csv_row`].
+    pub fn csv_header() -> &'static str {
+        "total,mean,min,max,percent50,percent90,percent99,coefficient_of_variation"
+    }
+
+    /// to_csv_row returns the report as one CSV row, so reports of many histograms
+    /// can be stacked into a CSV file under [`HistogramReport::csv_header`]. The
+    /// coefficient_of_variation column is left empty when it is undefined.
+    /// # Example
+    /// ```
+    /// use crate::stream_histogram::{Histogram, HistogramReport};
+    /// let mut histogram = Histogram::new(10);
+    /// for i in 1..=100 {
+    ///     histogram.add(i as f64);
+    /// }
+    /// let mut csv = String::from(HistogramReport::csv_header());
+    /// csv += "\n";
+    /// csv += &histogram.report().unwrap().to_csv_row();
+    /// ```
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.total,
+            self.mean,
+            self.min,
+            self.max,
+            self.percent50,
+            self.percent90,
+            self.percent99,
+            self.coefficient_of_variation
+                .map(|cv| cv.to_string())
+                .unwrap_or_default()
+        )
+    }
+}
+
+// approx_eq check whether two values are equal within the relative tolerance.
+fn approx_eq(a: f64, b: f64, rel_tol: f64) -> bool {
+    a == b || (a - b).abs() <= rel_tol * a.abs().max(b.abs())
+}
+
+// relative_change returns the change between two values relative to the larger magnitude.
+fn relative_change(a: f64, b: f64) -> f64 {
+    let scale = a.abs().max(b.abs());
+    if scale == 0.0 {
+        0.0
+    } else {
+        (a - b).abs() / scale
+    }
+}
+
+#[cfg(test)]
 mod tests {
+    use super::approx_eq;
     use super::Histogram;
-    use crate::HistogramReport;
+    use crate::{HistogramError, HistogramReport, HistogramStatus};
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.max_bins, 10);
+        histogram.add(1.0);
+        assert_eq!(histogram.total, 1);
+        assert_eq!(histogram.bins.len(), 1);
+
+        // same value located in same bin
+        histogram.add(1.0);
+        assert_eq!(histogram.total, 2);
+        assert_eq!(histogram.bins.len(), 1);
+
+        histogram.add(2.0);
+        assert_eq!(histogram.total, 3);
+        assert_eq!(histogram.bins.len(), 2);
+
+        let mut histogram = Histogram::new(10);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        assert_eq!(histogram.total, 100);
+        assert_eq!(histogram.bins.len(), 10);
+        assert_eq!(histogram.max_bins, 10);
+    }
+
+    #[test]
+    fn test_statistics_function() {
+        let mut histogram = Histogram::new(10);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+
+        let mean = histogram.mean();
+        assert!(mean.is_some());
+        let mean = mean.unwrap();
+        assert_eq!(mean, 50.5);
+
+        let cdf = histogram.cdf(100.0);
+        assert!(cdf.is_some());
+        let cdf = cdf.unwrap();
+        assert_eq!(cdf, 1.0);
+        let cdf = histogram.cdf(50.0);
+        assert!(cdf.is_some());
+        let cdf = cdf.unwrap();
+        assert_eq!(cdf, 0.45);
+
+        let quantile = histogram.quantile(0.50);
+        assert!(quantile.is_some());
+        let quantile = quantile.unwrap();
+        assert_eq!(quantile, 52.5);
+
+        let variance = histogram.variance();
+        assert!(variance.is_some());
+        let variance = variance.unwrap();
+        assert!((variance - 833.25).abs() < 1e-9);
+    }
+    #[test]
+    fn test_print() {
+        let mut histogram = Histogram::new(10);
+        for i in 1..=12 {
+            histogram.add(i as f64);
+        }
+        println!("{}", histogram);
+    }
+    #[test]
+    fn test_report() {
+        let mut histogram = Histogram::new(10);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        let report = HistogramReport::new(&histogram);
+        assert!(report.is_some());
+        let report = report.unwrap();
+
+        assert_eq!(report.max, 100.00);
+        assert_eq!(report.min, 1.00);
+        assert_eq!(report.mean, 50.5);
+        assert_eq!(report.total, 100);
+        assert_eq!(report.percent50, 52.5);
+        assert_eq!(report.percent90, 86.0);
+        assert_eq!(report.percent99, 96.5);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = Histogram::new(10);
+        let mut second = Histogram::new(20);
+        for i in 1..=100 {
+            first.add(i as f64);
+            second.add((i * 2) as f64);
+        }
+        let total = first.total + second.total;
+
+        let mut merged = first.clone();
+        merged += second.clone();
+        assert_eq!(merged.total, total);
+        assert_eq!(merged.bins.len(), 10);
+
+        let combined = first + second;
+        assert_eq!(combined.total, total);
+        assert_eq!(combined.max_bins, 10);
+        assert_eq!(combined.min, Some(1.0));
+        assert_eq!(combined.max, Some(200.0));
+        assert_eq!(combined.bins.len(), 10);
+    }
+
+    #[test]
+    fn test_with_bounds() {
+        let mut histogram = Histogram::with_bounds(10, 0.0, 10.0);
+        assert_eq!(histogram.bounds, Some((0.0, 10.0)));
+        histogram.add(-1.0);
+        assert_eq!(histogram.clamped_count(), 1);
+        assert_eq!(histogram.min, Some(0.0));
+        histogram.add(5.0);
+        assert_eq!(histogram.clamped_count(), 1);
+        histogram.add(11.0);
+        histogram.add(1e9);
+        assert_eq!(histogram.clamped_count(), 3);
+        assert_eq!(histogram.max, Some(10.0));
+        assert_eq!(histogram.total, 4);
+
+        let values: Vec<(f64, usize)> = histogram.bins.iter().map(|i| (i.value, i.count)).collect();
+        assert_eq!(values, vec![(0.0, 1), (5.0, 1), (10.0, 2)]);
+
+        // reversed bounds are accepted
+        let histogram = Histogram::with_bounds(10, 10.0, 0.0);
+        assert_eq!(histogram.bounds, Some((0.0, 10.0)));
+    }
+
+    #[test]
+    fn test_quantile_interval() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.quantile_interval(0.5), None);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        for q in [0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99].iter() {
+            let (low, high) = histogram.quantile_interval(*q).unwrap();
+            assert!(low < high);
+            assert_eq!(Some(high), histogram.quantile(*q));
+        }
+        assert_eq!(histogram.quantile_interval(0.0).unwrap().0, 1.0);
+        let (low, high) = histogram.quantile_interval(0.5).unwrap();
+        assert_eq!(high, 52.5);
+        assert!(low < 50.5);
+        assert_eq!(histogram.quantile_interval(1.1), None);
+    }
+
+    #[test]
+    fn test_merge_count() {
+        let mut histogram = Histogram::new(10);
+        for i in 1..=10 {
+            histogram.add(i as f64);
+        }
+        assert_eq!(histogram.merge_count(), 0);
+        // same value located in same bin, no merge needed
+        histogram.add(10.0);
+        assert_eq!(histogram.merge_count(), 0);
+        histogram.add(11.0);
+        assert_eq!(histogram.merge_count(), 1);
+        for i in 12..=100 {
+            histogram.add(i as f64);
+        }
+        assert_eq!(histogram.merge_count(), 90);
+    }
+
+    #[test]
+    fn test_weighted_quantile() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.weighted_quantile(0.5), None);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        let median = histogram.weighted_quantile(0.5).unwrap();
+        assert!((median - 50.5).abs() < (histogram.quantile(0.5).unwrap() - 50.5).abs());
+        assert!((median - 50.5).abs() < 1.0);
+        assert_eq!(histogram.weighted_quantile(0.0), Some(1.0));
+        assert_eq!(histogram.weighted_quantile(1.0), Some(100.0));
+        assert_eq!(histogram.weighted_quantile(1.5), None);
+        let mut last = 0.0;
+        for i in 0..=100 {
+            let value = histogram.weighted_quantile(i as f64 / 100.0).unwrap();
+            assert!(value >= last);
+            last = value;
+        }
+    }
+
+    #[test]
+    fn test_split_at() {
+        let mut histogram = Histogram::new(20);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        for x in [-10.0, 1.0, 30.0, 50.5, 77.0, 100.0, 1000.0].iter() {
+            let (lower, upper) = histogram.split_at(*x);
+            assert_eq!(lower.total + upper.total, histogram.total);
+            assert_eq!(lower.max_bins, histogram.max_bins);
+            assert_eq!(upper.max_bins, histogram.max_bins);
+            assert!(lower.bins.iter().all(|i| i.value <= *x));
+            assert!(upper.bins.iter().all(|i| i.value > *x));
+        }
+        let (lower, upper) = histogram.split_at(30.0);
+        assert_eq!(lower.min, Some(1.0));
+        assert_eq!(upper.max, Some(100.0));
+        assert!((lower.total as i64 - 30).abs() <= 2);
+
+        let (lower, upper) = histogram.split_at(1000.0);
+        assert_eq!(lower.total, 100);
+        assert_eq!(upper.total, 0);
+        assert_eq!(upper.min, None);
+    }
+
+    #[test]
+    fn test_smoothed() {
+        fn count_variance(counts: &[f64]) -> f64 {
+            let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+            counts.iter().map(|c| (c - mean) * (c - mean)).sum::<f64>() / counts.len() as f64
+        }
+        let mut histogram = Histogram::new(20);
+        assert!(histogram.smoothed(3).is_empty());
+        for i in 1..=20 {
+            let repeat = if i % 2 == 0 { 1 } else { 9 };
+            for _ in 0..repeat {
+                histogram.add(i as f64);
+            }
+        }
+        let raw: Vec<f64> = histogram.smoothed(1).iter().map(|(_, c)| *c).collect();
+        let smoothed = histogram.smoothed(3);
+        let counts: Vec<f64> = smoothed.iter().map(|(_, c)| *c).collect();
+        assert_eq!(smoothed.len(), 20);
+        assert_eq!(smoothed[0], (1.0, 5.0));
+        assert!(count_variance(&counts) < count_variance(&raw));
+        assert_eq!(histogram.total, 100);
+    }
+
+    #[test]
+    fn test_suggest_max_bins() {
+        let coarse = Histogram::suggest_max_bins(10.0, 1000.0);
+        let fine = Histogram::suggest_max_bins(1.0, 1000.0);
+        let finer = Histogram::suggest_max_bins(0.1, 1000.0);
+        assert!(coarse < fine && fine < finer);
+        assert_eq!(fine, 500);
+        assert_eq!(Histogram::suggest_max_bins(1000.0, 1.0), 10);
+        assert_eq!(Histogram::suggest_max_bins(1e-9, 1e9), 100_000);
+        assert_eq!(Histogram::suggest_max_bins(0.0, 1000.0), 100);
+        assert_eq!(Histogram::suggest_max_bins(f64::NAN, 1000.0), 100);
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(
+            format!("{:?}", histogram),
+            "Histogram { max_bins: 10, total: 0, min: None, max: None, bins: (0) [] }"
+        );
+        for i in 1..=3 {
+            histogram.add(i as f64);
+        }
+        assert_eq!(
+            format!("{:?}", histogram),
+            "Histogram { max_bins: 10, total: 3, min: Some(1.0), max: Some(3.0), bins: (3) [1: 1, 2: 1, 3: 1] }"
+        );
+        for i in 4..=100 {
+            histogram.add(i as f64);
+        }
+        let compact = format!("{:?}", histogram);
+        assert!(compact.contains("bins: (10) [4.5: 8, 12.5: 8, 22: 11, .., 86: 13, 96.5: 8]"));
+        let full = format!("{:#?}", histogram);
+        assert!(full.contains("merges: 90"));
+        assert_eq!(full.matches("count: ").count(), 10);
+    }
+
+    #[test]
+    fn test_peak_count() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.peak_count(), None);
+        for i in 1..=20 {
+            histogram.add(i as f64);
+        }
+        for _ in 0..50 {
+            histogram.add(7.0);
+        }
+        assert_eq!(histogram.peak_count(), Some(51));
+    }
+
+    #[test]
+    fn test_coefficient_of_variation() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.coefficient_of_variation(), None);
+        for _ in 0..10 {
+            histogram.add(2.0);
+            histogram.add(4.0);
+        }
+        // mean 3, stddev 1
+        assert!(approx_eq(histogram.stddev().unwrap(), 1.0, 1e-12));
+        let cv = histogram.coefficient_of_variation().unwrap();
+        assert!(approx_eq(cv, 1.0 / 3.0, 1e-12));
+        assert_eq!(
+            histogram.report().unwrap().coefficient_of_variation,
+            Some(cv)
+        );
+
+        let mut histogram = Histogram::new(10);
+        histogram.add(-1.0);
+        histogram.add(1.0);
+        assert_eq!(histogram.coefficient_of_variation(), None);
+        assert_eq!(histogram.report().unwrap().coefficient_of_variation, None);
+    }
+
+    #[test]
+    fn test_to_hdr_buckets() {
+        let mut histogram = Histogram::new(50);
+        assert!(histogram.to_hdr_buckets(1.0, 1000.0, 1).is_empty());
+        for i in 0..10000 {
+            histogram.add((i % 997) as f64 * 1.5 - 10.0);
+        }
+        let buckets = histogram.to_hdr_buckets(1.0, 1000.0, 1);
+        assert_eq!(buckets.iter().map(|(_, c)| c).sum::<u64>(), histogram.total);
+        assert!(buckets.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(buckets[0].0 >= 0.0);
+        assert!(buckets.iter().all(|(lower, _)| *lower <= 1000.0));
+
+        // 1 significant figure gives 32 linear buckets of width 1, then the width doubles
+        let mut histogram = Histogram::new(20);
+        for v in [3.5, 31.9, 33.0, 65.0, 100.0, 101.0].iter() {
+            histogram.add(*v);
+        }
+        let buckets = histogram.to_hdr_buckets(1.0, 1000.0, 1);
+        assert_eq!(
+            buckets,
+            vec![(3.0, 1), (31.0, 1), (32.0, 1), (64.0, 1), (100.0, 2)]
+        );
+
+        assert!(histogram.to_hdr_buckets(0.0, 1000.0, 1).is_empty());
+        assert!(histogram.to_hdr_buckets(1.0, 1.5, 1).is_empty());
+        assert!(histogram.to_hdr_buckets(1.0, 1000.0, 6).is_empty());
+    }
+
+    #[test]
+    fn test_merge_tie_break() {
+        fn bins(values: &[f64]) -> Vec<(f64, usize)> {
+            let mut histogram = Histogram::new(10);
+            for v in values {
+                histogram.add(*v);
+            }
+            histogram.bins.iter().map(|i| (i.value, i.count)).collect()
+        }
+        // all gaps are equal, the pair (0, 1) holds more values so (1, 2) is merged
+        let mut values = vec![0.0, 0.0, 0.0];
+        values.extend((1..=10).map(|i| i as f64));
+        let result = bins(&values);
+        assert_eq!(result[0], (0.0, 3));
+        assert_eq!(result[1], (1.5, 2));
+        assert_eq!(result.len(), 10);
+        assert_eq!(result, bins(&values));
+
+        // all gaps and counts are equal except the top pair, the lowest pair is merged
+        let mut values = vec![10.0, 10.0, 10.0];
+        values.extend((0..=9).rev().map(|i| i as f64));
+        let result = bins(&values);
+        assert_eq!(result[0], (0.5, 2));
+        assert_eq!(result[9], (10.0, 3));
+        assert_eq!(result, bins(&values));
+    }
+
+    #[test]
+    fn test_pmf() {
+        let mut histogram = Histogram::new(10);
+        assert!(histogram.pmf().is_empty());
+        for i in 1..=1000 {
+            histogram.add((i % 37) as f64);
+        }
+        let pmf = histogram.pmf();
+        assert_eq!(pmf.len(), 10);
+        assert!((pmf.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(pmf.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_remove_bin() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.remove_bin(0), None);
+        for i in 1..=5 {
+            histogram.add(i as f64);
+        }
+        histogram.add(5.0);
+        assert_eq!(histogram.remove_bin(5), None);
+        assert_eq!(histogram.remove_bin(4), Some((5.0, 2)));
+        assert_eq!(histogram.max(), Some(4.0));
+        assert_eq!(histogram.min(), Some(1.0));
+        assert_eq!(histogram.total, 4);
+        assert_eq!(histogram.report().unwrap().max, 4.0);
+
+        assert_eq!(histogram.remove_bin(1), Some((2.0, 1)));
+        assert_eq!(histogram.min(), Some(1.0));
+        assert_eq!(histogram.max(), Some(4.0));
+
+        assert_eq!(histogram.remove_bin(0), Some((1.0, 1)));
+        assert_eq!(histogram.min(), Some(3.0));
+        histogram.remove_bin(0);
+        histogram.remove_bin(0);
+        assert_eq!(histogram.total, 0);
+        assert_eq!(histogram.min(), None);
+        assert_eq!(histogram.max(), None);
+    }
+
+    #[test]
+    fn test_percentile_table() {
+        let mut histogram = Histogram::new(20);
+        let table = histogram.percentile_table();
+        assert_eq!(table.lines().count(), 9);
+        assert!(table.starts_with("count                  0\n"));
+        assert!(table.contains("p99.9                  -\n"));
+
+        for i in 1..=1000 {
+            histogram.add(i as f64);
+        }
+        let table = histogram.percentile_table();
+        for label in [
+            "count", "mean", "stddev", "p50", "p75", "p90", "p95", "p99", "p99.9",
+        ]
+        .iter()
+        {
+            assert!(table.lines().any(|line| line.starts_with(label)));
+        }
+        assert!(table.contains("mean             500.500\n"));
+        assert!(table.lines().all(|line| line.len() == 24));
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.rate(), None);
+        // an ignored NaN is not timed
+        histogram.add_timed(f64::NAN, 0.0);
+        assert_eq!(histogram.timestamps, None);
+        histogram.add_timed(1.0, 100.0);
+        assert_eq!(histogram.rate(), None);
+        // 4 values per second for 10 seconds, arriving out of order
+        for i in (1..=40).rev() {
+            histogram.add_timed((i % 5) as f64, 100.0 + i as f64 * 0.25);
+        }
+        assert_eq!(histogram.timestamps, Some((100.0, 110.0)));
+        assert_eq!(histogram.rate(), Some(4.0));
+
+        // untimed values are not part of the rate
+        histogram.add(3.0);
+        assert_eq!(histogram.rate(), Some(4.0));
+        assert_eq!(histogram.total, 42);
+    }
+
+    #[test]
+    fn test_shape_distance() {
+        let mut small = Histogram::new(20);
+        let mut large = Histogram::new(20);
+        let mut other = Histogram::new(20);
+        assert!(small.normalized_shape().is_empty());
+        assert_eq!(small.shape_distance(&large), None);
+        for i in 1..=1000 {
+            let v = ((i * 7919) % 1000) as f64;
+            small.add(v * v);
+            large.add(v * v * 250.0 + 40.0);
+            other.add(v);
+        }
+        let shape = small.normalized_shape();
+        assert_eq!(shape.len(), 32);
+        assert!((shape.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(small.shape_distance(&large).unwrap() < 1e-9);
+        assert!(small.shape_distance(&other).unwrap() > 0.1);
+        assert_eq!(small.shape_distance(&other), other.shape_distance(&small));
+    }
+
+    #[test]
+    fn test_saturation() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static SATURATED: AtomicUsize = AtomicUsize::new(0);
+
+        let mut histogram = Histogram::new(10);
+        histogram.on_saturation(|value| {
+            assert_eq!(value, 1.0);
+            SATURATED.fetch_add(1, Ordering::SeqCst);
+        });
+        histogram.add_weighted(1.0, usize::MAX - 1);
+        histogram.add(1.0);
+        assert_eq!(histogram.bins.front().unwrap().count, usize::MAX);
+        assert_eq!(SATURATED.load(Ordering::SeqCst), 0);
+        histogram.add(1.0);
+        histogram.add_weighted(1.0, 10);
+        assert_eq!(histogram.bins.front().unwrap().count, usize::MAX);
+        assert_eq!(SATURATED.load(Ordering::SeqCst), 2);
+        assert_eq!(histogram.mean(), Some(1.0));
+
+        // weighted values behave like repeated values
+        let mut weighted = Histogram::new(10);
+        let mut repeated = Histogram::new(10);
+        weighted.add_weighted(2.0, 0);
+        assert_eq!(weighted.total, 0);
+        for i in 1..=30 {
+            weighted.add_weighted(i as f64, 3);
+            for _ in 0..3 {
+                repeated.add(i as f64);
+            }
+        }
+        assert_eq!(weighted.total, repeated.total);
+        assert_eq!(weighted.mean(), repeated.mean());
+    }
+
+    #[test]
+    fn test_downsample() {
+        let mut histogram = Histogram::new(20);
+        histogram.downsample(10);
+        assert_eq!(histogram.total, 0);
+        for i in 1..=10000 {
+            histogram.add(((i * 31) % 97) as f64);
+        }
+        let before = histogram.pmf();
+        histogram.downsample(20000);
+        assert_eq!(histogram.total, 10000);
+
+        histogram.downsample(1000);
+        assert_eq!(histogram.total, 1000);
+        assert_eq!(
+            histogram.bins.iter().map(|i| i.count as u64).sum::<u64>(),
+            1000
+        );
+        let after = histogram.pmf();
+        assert_eq!(before.len(), after.len());
+        for (b, a) in before.iter().zip(after.iter()) {
+            assert_eq!(b.0, a.0);
+            assert!((b.1 - a.1).abs() <= 1.0 / 1000.0);
+        }
+
+        // bins rounded to zero are dropped and the extremes follow
+        let mut histogram = Histogram::new(10);
+        histogram.add(1.0);
+        histogram.add_weighted(5.0, 98);
+        histogram.add(9.0);
+        assert!(histogram.is_exact());
+        histogram.downsample(10);
+        assert!(!histogram.is_exact());
+        assert_eq!(histogram.total, 10);
+        assert_eq!(histogram.bins.len(), 1);
+        assert_eq!(histogram.min(), Some(5.0));
+        assert_eq!(histogram.max(), Some(5.0));
+    }
+
+    #[test]
+    fn test_report_approx_eq() {
+        let mut histogram = Histogram::new(10);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        let report = histogram.report().unwrap();
+        let mut other = report.clone();
+        assert!(report.approx_eq(&other, 0.0));
+        other.mean *= 1.0 + 1e-12;
+        other.percent99 += 1e-10;
+        assert!(!report.approx_eq(&other, 0.0));
+        assert!(report.approx_eq(&other, 1e-9));
+        other.total += 1;
+        assert!(!report.approx_eq(&other, 1e-9));
+        assert!(report.approx_eq(&other, 0.02));
+        other.coefficient_of_variation = None;
+        assert!(!report.approx_eq(&other, 0.02));
+    }
+
+    #[test]
+    fn test_meets_slo() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.meets_slo(0.99, 100.0), None);
+        for i in 1..=1000 {
+            histogram.add((i % 100) as f64);
+        }
+        histogram.add(5000.0);
+        assert_eq!(histogram.meets_slo(0.9, 100.0), Some(true));
+        assert_eq!(histogram.meets_slo(0.99, 100.0), Some(true));
+        assert_eq!(histogram.meets_slo(1.0, 100.0), Some(false));
+        assert_eq!(histogram.meets_slo(0.5, 20.0), Some(false));
+    }
+
+    #[test]
+    fn test_significant_bins() {
+        let mut histogram = Histogram::new(20);
+        assert!(histogram.significant_bins(0.1).is_empty());
+        for i in 1..=15 {
+            histogram.add(i as f64);
+        }
+        histogram.add_weighted(4.0, 30);
+        histogram.add_weighted(12.0, 55);
+        assert_eq!(histogram.significant_bins(0.1), vec![(12.0, 56), (4.0, 31)]);
+        assert_eq!(histogram.significant_bins(0.0).len(), 15);
+        assert!(histogram.significant_bins(0.9).is_empty());
+    }
+
+    #[test]
+    fn test_centroid() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.centroid(), None);
+        for i in 1..=100000 {
+            histogram.add(((i * 7919) % 10007) as f64 / 7.0);
+        }
+        assert!(histogram.merge_count() > 90000);
+        let centroid = histogram.centroid().unwrap();
+        let mean = histogram.mean().unwrap();
+        assert!((centroid - mean).abs() <= 1e-9 * mean.abs());
+    }
+
+    #[test]
+    fn test_quantile_plateau() {
+        let mut histogram = Histogram::new(100);
+        for i in 1..=10 {
+            histogram.add(i as f64);
+        }
+        histogram.add_weighted(50.0, 18);
+        for i in 51..=122 {
+            histogram.add(i as f64);
+        }
+        assert_eq!(histogram.total, 100);
+        // 0.07 * 100 is 7.000000000000001
+        assert_eq!(histogram.quantile(0.07), Some(7.0));
+        assert_eq!(histogram.quantile(0.1), Some(10.0));
+        for q in [0.105, 0.11, 0.14, 0.2, 0.25, 0.27].iter() {
+            assert_eq!(histogram.quantile(*q), Some(50.0));
+        }
+        // 0.28 * 100 is 28.000000000000004, still the end of the plateau
+        assert_eq!(histogram.quantile(0.28), Some(50.0));
+        assert_eq!(histogram.quantile(0.281), Some(51.0));
+        assert_eq!(histogram.quantile(0.29), Some(51.0));
+        assert_eq!(histogram.quantile(0.0), Some(1.0));
+        assert_eq!(histogram.quantile(1.0), Some(122.0));
+        assert_eq!(histogram.quantile(1.01), None);
+        assert_eq!(histogram.quantile(f64::NAN), None);
+
+        let mut histogram = Histogram::new(10);
+        histogram.add_weighted(3.0, 1000);
+        histogram.add(1.0);
+        histogram.add(5.0);
+        for i in 1..100 {
+            assert_eq!(histogram.quantile(i as f64 / 100.0), Some(3.0));
+        }
+    }
+
+    #[test]
+    fn test_report_csv() {
+        let mut histogram = Histogram::new(10);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        let report = histogram.report().unwrap();
+        let row = report.to_csv_row();
+        assert_eq!(
+            HistogramReport::csv_header().split(',').count(),
+            row.split(',').count()
+        );
+        let cv = report.coefficient_of_variation.unwrap();
+        assert_eq!(row, format!("100,50.5,1,100,52.5,86,96.5,{}", cv));
+
+        let mut centered = Histogram::new(10);
+        centered.add(-1.0);
+        centered.add(1.0);
+        let row = centered.report().unwrap().to_csv_row();
+        assert!(row.ends_with(','));
+        assert_eq!(
+            HistogramReport::csv_header().split(',').count(),
+            row.split(',').count()
+        );
+    }
+
+    #[test]
+    fn test_add_duration() {
+        let mut histogram = Histogram::new(10);
+        histogram.add_duration(1.0, 0.0);
+        histogram.add_duration(1.0, -1.0);
+        histogram.add_duration(1.0, f64::NAN);
+        histogram.add_duration(1.0, 0.0001);
+        assert_eq!(histogram.total, 0);
+
+        // 30 is held twice as long as 0
+        histogram.add_duration(0.0, 1.5);
+        histogram.add_duration(30.0, 3.0);
+        assert_eq!(histogram.total, 4500);
+        assert_eq!(histogram.mean(), Some(20.0));
+        assert_eq!(histogram.quantile(0.5), Some(30.0));
+        assert_eq!(histogram.cdf(0.0), Some(1.0 / 3.0));
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut histogram = Histogram::new(30);
+        for i in 1..=30 {
+            histogram.add(i as f64);
+        }
+        histogram.compact();
+        assert_eq!(histogram.bins.len(), 30);
+        assert_eq!(histogram.merge_count(), 0);
+
+        histogram.max_bins = 10;
+        histogram.compact();
+        assert_eq!(histogram.bins.len(), 10);
+        assert_eq!(histogram.merge_count(), 20);
+        assert_eq!(histogram.total, 30);
+        assert_eq!(histogram.bins.iter().map(|i| i.count).sum::<usize>(), 30);
+        assert_eq!(histogram.mean(), Some(15.5));
+    }
+
+    #[test]
+    fn test_summarize() {
+        let mut histogram = Histogram::new(20);
+        let summary = histogram.summarize(&[0.5, 0.9]);
+        assert_eq!(summary.count, 0);
+        assert_eq!(summary.mean, None);
+        assert_eq!(summary.quantiles, vec![None, None]);
+
+        for i in 1..=10000 {
+            histogram.add(((i * 7919) % 1000) as f64 / 3.0);
+        }
+        let quantiles = [0.99, 0.5, 0.0, 1.0, 0.25, 0.9, 1.5, 0.5];
+        let summary = histogram.summarize(&quantiles);
+        assert_eq!(summary.count, histogram.total);
+        assert_eq!(summary.min, histogram.min());
+        assert_eq!(summary.max, histogram.max());
+        assert_eq!(summary.mean, histogram.mean());
+        assert_eq!(summary.variance, histogram.variance());
+        for (q, value) in quantiles.iter().zip(summary.quantiles.iter()) {
+            assert_eq!(*value, histogram.quantile(*q));
+        }
+
+        // a large offset would cancel a plain sum of squares
+        let mut offset = Histogram::new(20);
+        for i in 0..1000 {
+            offset.add(1e9 + (i % 3) as f64);
+        }
+        let summary = offset.summarize(&[0.5]);
+        assert_eq!(summary.variance, offset.variance());
+        assert!((summary.variance.unwrap() - 0.666999).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_exact_until_full() {
+        use crate::testutil::exact_quantile;
+
+        let mut histogram = Histogram::new(50);
+        let mut values = Vec::new();
+        for i in 1..=50 {
+            let v = ((i * 7919) % 101) as f64 / 4.0;
+            histogram.add(v);
+            values.push(v);
+        }
+        assert!(histogram.is_exact());
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for i in 0..=100 {
+            let q = i as f64 / 100.0;
+            assert_eq!(histogram.quantile(q), Some(exact_quantile(&values, q)));
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!((histogram.mean().unwrap() - mean).abs() < 1e-9);
+
+        histogram.add(1000.0);
+        assert!(!histogram.is_exact());
+    }
+
+    #[test]
+    fn test_gini() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.gini(), None);
+        histogram.add_weighted(0.0, 5);
+        assert_eq!(histogram.gini(), None);
+
+        let mut uniform = Histogram::new(10);
+        uniform.add_weighted(42.0, 1000);
+        assert!(uniform.gini().unwrap().abs() < 1e-9);
+
+        let mut spread = Histogram::new(10);
+        for i in 1..=1000 {
+            spread.add((i % 10 + 95) as f64);
+        }
+        assert!(spread.gini().unwrap() < 0.05);
+
+        let mut concentrated = Histogram::new(10);
+        concentrated.add_weighted(1.0, 990);
+        concentrated.add_weighted(100000.0, 10);
+        assert!(concentrated.gini().unwrap() > 0.9);
+
+        concentrated.add(-1.0);
+        assert_eq!(concentrated.gini(), None);
+    }
+
+    #[test]
+    fn test_into_iterator() {
+        let mut histogram = Histogram::new(10);
+        for i in 1..=100 {
+            histogram.add((i % 5) as f64);
+        }
+        let mut values = vec![];
+        let mut total = 0;
+        for (value, count) in &histogram {
+            values.push(value);
+            total += count;
+        }
+        assert_eq!(values, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(total, 100);
+        assert_eq!(histogram.iter_bins().len(), 5);
+        assert_eq!(histogram.iter_bins().next_back(), Some((4.0, 20)));
+    }
+
+    #[test]
+    fn test_drift_score() {
+        let mut histogram = Histogram::new(20);
+        for i in 1..=1000 {
+            histogram.add((i % 100) as f64);
+        }
+        let previous = histogram.report().unwrap();
+        assert!(histogram.report().unwrap().drift_score(&previous) < 1e-12);
+
+        for i in 1..=10 {
+            histogram.add((i % 100) as f64);
+        }
+        let small = histogram.report().unwrap().drift_score(&previous);
+        assert!(small < 0.05);
+
+        for _ in 1..=1000 {
+            histogram.add(500.0);
+        }
+        let large = histogram.report().unwrap().drift_score(&previous);
+        assert!(large > small);
+        assert!(large > 0.5);
+    }
+
+    #[test]
+    fn test_from_uniform_buckets() {
+        let edges: Vec<f64> = (0..=10).map(|i| i as f64 * 10.0).collect();
+        let counts = vec![1, 2, 3, 4, 0, 0, 4, 3, 2, 1];
+        let histogram = Histogram::from_uniform_buckets(&edges, &counts, 20);
+        assert_eq!(histogram.iter_bins().len(), 8);
+        assert_eq!(histogram.report().unwrap().total, 20);
+        assert!((histogram.mean().unwrap() - 50.0).abs() < 1e-9);
+        assert_eq!(histogram.min(), Some(5.0));
+        assert_eq!(histogram.max(), Some(95.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_uniform_buckets_mismatch() {
+        Histogram::from_uniform_buckets(&[0.0, 1.0], &[1, 2], 10);
+    }
+
+    #[test]
+    fn test_ema_decay() {
+        let mut histogram = Histogram::builder().max_bins(10).ema_decay(0.01).build();
+        for _ in 1..=1000 {
+            histogram.add(1.0);
+        }
+        let total = histogram.report().unwrap().total;
+        assert!(total < 200);
+        assert_eq!(histogram.quantile(0.5), Some(1.0));
+        for _ in 1..=300 {
+            histogram.add(100.0);
+        }
+        assert_eq!(histogram.quantile(0.5), Some(100.0));
+        let old = histogram.iter_bins().next().unwrap();
+        assert!(old.0 < 100.0 && old.1 < 10);
+        for _ in 1..=1000 {
+            histogram.add(100.0);
+        }
+        assert_eq!(histogram.min(), Some(100.0));
+
+        let mut plain = Histogram::builder().max_bins(10).build();
+        for _ in 1..=1000 {
+            plain.add(1.0);
+        }
+        assert_eq!(plain.report().unwrap().total, 1000);
+    }
+
+    #[test]
+    fn test_quantile_overshoot() {
+        let mut histogram = Histogram::new(100);
+        assert_eq!(histogram.quantile(1.0), None);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        // 56 shares of 1/56 add up to 1.000000000000001
+        let q: f64 = (0..56).map(|_| 1.0 / 56.0).sum();
+        assert!(q > 1.0);
+        assert_eq!(histogram.quantile(q), Some(100.0));
+        assert_eq!(histogram.quantile(0.1 * 3.0 / 0.3), Some(100.0));
+        assert_eq!(histogram.quantile(1.5), None);
+    }
+
+    #[test]
+    fn test_add_buffered() {
+        let mut buffered = Histogram::new(20);
+        let mut histogram = Histogram::new(20);
+        let mut adder = buffered.buffered();
+        for i in 1..=1005 {
+            adder.add((i % 100) as f64);
+            histogram.add((i % 100) as f64);
+        }
+        drop(adder);
+        // 1005 is not a multiple of the buffer size, the rest is flushed on drop
+        let report = buffered.report().unwrap();
+        assert_eq!(report.total, 1005);
+        assert_eq!(report.min, 0.0);
+        assert_eq!(report.max, 99.0);
+        assert!(buffered.iter_bins().len() <= 20);
+        assert!((report.mean - histogram.mean().unwrap()).abs() < 1e-9);
+        assert!((report.percent50 - histogram.quantile(0.5).unwrap()).abs() < 10.0);
+
+        let mut adder = buffered.buffered();
+        adder.add(1000.0);
+        adder.flush();
+        adder.add(f64::NAN);
+        drop(adder);
+        assert_eq!(buffered.report().unwrap().total, 1006);
+        assert_eq!(buffered.max(), Some(1000.0));
+    }
+
+    #[test]
+    fn test_tail_reserve() {
+        use crate::testutil::exact_quantile;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::from_seed([7; 32]);
+        // pareto distributed values, a heavy tail above a dense body
+        let mut values: Vec<f64> = (0..20000)
+            .map(|_| 1.0 / (1.0 - rng.gen::<f64>()).powf(1.0 / 1.5))
+            .collect();
+        let mut plain = Histogram::new(20);
+        let mut reserved = Histogram::builder().max_bins(20).tail_reserve(0.5).build();
+        for v in values.iter() {
+            plain.add(*v);
+            reserved.add(*v);
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let exact = exact_quantile(&values, 0.99);
+        let error = |h: &Histogram| (h.quantile(0.99).unwrap() - exact).abs() / exact;
+        assert!(error(&reserved) < error(&plain));
+        assert_eq!(reserved.iter_bins().len(), 20);
+
+        // compacting many bins at once keeps the tails like merging one pair at a time
+        let mut wide = Histogram::builder().max_bins(400).tail_reserve(0.5).build();
+        wide.add_slice(&values);
+        let mut expected = wide.clone();
+        while expected.bins.len() > 20 {
+            expected.merge_closest();
+        }
+        wide.shrink_to(20);
+        assert!(wide.iter_bins().eq(expected.iter_bins()));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.sparkline(), "");
+        for i in 1..=1000 {
+            histogram.add((i % 100) as f64);
+        }
+        let line = histogram.sparkline();
+        assert_eq!(line.chars().count(), 20);
+        assert!(line.chars().all(|c| "▁▂▃▄▅▆▇█".contains(c)));
+        assert!(line.contains('█'));
+
+        let mut wide = Histogram::new(100);
+        for i in 1..=1000 {
+            wide.add((i % 100) as f64);
+        }
+        assert!(wide.sparkline().chars().count() <= 32);
+    }
+
+    #[test]
+    fn test_bin_variance() {
+        let mut histogram = Histogram::new(10);
+        let values: Vec<f64> = (1..=10000).map(|i| ((i * 7919) % 1000) as f64).collect();
+        for v in values.iter() {
+            histogram.add(*v);
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let exact =
+            values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+        // the variance of the bin values alone, without the spread inside the bins
+        let centers = histogram
+            .iter_bins()
+            .map(|(v, c)| c as f64 * (v - mean) * (v - mean))
+            .sum::<f64>()
+            / 10000.0;
+        let variance = histogram.variance().unwrap();
+        assert!((variance - exact).abs() / exact < 1e-9);
+        assert!((centers - exact).abs() / exact > 0.001);
+        let summary = histogram.summarize(&[]);
+        assert_eq!(summary.variance, Some(variance));
+    }
+
+    #[test]
+    fn test_clamp_total() {
+        let mut histogram = Histogram::new(20);
+        let mut reference = Histogram::new(20);
+        histogram.clamp_total(5000);
+        for i in 1..=100000 {
+            histogram.add((i % 100) as f64);
+            reference.add((i % 100) as f64);
+            let total = histogram.report().unwrap().total;
+            assert!(total <= 5000);
+            assert!(i < 5000 || total >= 4750);
+        }
+        assert!(histogram.shape_distance(&reference).unwrap() < 0.05);
+        let (clamped, reference) = (histogram.report().unwrap(), reference.report().unwrap());
+        assert!((clamped.mean - reference.mean).abs() < 1.0);
+        assert!((clamped.percent50 - reference.percent50).abs() < 5.0);
+
+        histogram.clamp_total(100);
+        assert_eq!(histogram.report().unwrap().total, 95);
+        histogram.clamp_total(0);
+        for _ in 1..=1000 {
+            histogram.add(1.0);
+        }
+        assert!(histogram.report().unwrap().total > 1000);
+    }
+
+    #[test]
+    fn test_bin_at() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.bin_at(0), None);
+        for i in (1..=100).rev() {
+            histogram.add(i as f64);
+        }
+        let first = histogram.iter_bins().next();
+        assert_eq!(histogram.bin_at(0), first);
+        assert!(histogram
+            .iter_bins()
+            .all(|(v, _)| v >= histogram.bin_at(0).unwrap().0));
+        assert_eq!(histogram.bin_at(9), histogram.iter_bins().next_back());
+        assert_eq!(histogram.bin_at(10), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            Histogram::try_new(0),
+            Err(HistogramError::InvalidMaxBins(0))
+        ));
+        let mut histogram = Histogram::try_new(3).unwrap();
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        assert_eq!(histogram.iter_bins().len(), 3);
+        assert_eq!(Histogram::new(0).iter_bins().len(), 0);
+
+        let mut histogram = Histogram::new(10);
+        assert!(matches!(histogram.try_report(), Err(HistogramError::Empty)));
+        for v in [f64::INFINITY, f64::NEG_INFINITY].iter() {
+            assert!(matches!(
+                histogram.try_add(*v),
+                Err(HistogramError::InvalidValue(_))
+            ));
+        }
+        assert!(histogram.try_add(f64::NAN).is_ok());
+        assert!(histogram.try_add(1.0).is_ok());
+        assert_eq!(histogram.try_report().unwrap().total, 1);
+
+        histogram.add_weighted(2.0, usize::MAX);
+        assert!(matches!(histogram.try_add(3.0), Err(HistogramError::Full)));
+        assert_eq!(
+            HistogramError::Full.to_string(),
+            "histogram total count is saturated"
+        );
+    }
+
+    #[test]
+    fn test_top_values() {
+        let mut histogram = Histogram::builder().max_bins(10).top_k(3).build();
+        for i in 1..=2000 {
+            histogram.add(i as f64 + 0.5);
+            histogram.add(200.0);
+            histogram.add(200.0);
+            histogram.add(700.0);
+            if i % 2 == 0 {
+                histogram.add(700.0);
+            }
+            if i % 4 != 0 {
+                histogram.add(-3.0);
+            }
+        }
+        let top = histogram.top_values();
+        let values: Vec<f64> = top.iter().map(|(v, _)| *v).collect();
+        assert_eq!(values, vec![200.0, 700.0, -3.0]);
+        assert!(top[0].1 >= 4000);
+        assert!(top[1].1 >= 3000);
+        assert!(top[2].1 >= 1500);
+
+        let mut merged = histogram.clone();
+        merged.merge(&histogram);
+        assert_eq!(merged.top_values()[0].0, 200.0);
+        assert!(Histogram::new(10).top_values().is_empty());
+    }
+
+    #[test]
+    fn test_cdf_area_distance() {
+        let mut first = Histogram::new(20);
+        let mut second = Histogram::new(20);
+        let mut third = Histogram::new(20);
+        assert_eq!(first.cdf_area_distance(&second), None);
+        for i in 1..=1000 {
+            first.add((i % 100) as f64);
+            second.add((i % 100) as f64 + 10.0);
+            third.add((i % 100) as f64 + 20.0);
+        }
+        assert_eq!(first.cdf_area_distance(&first.clone()), Some(0.0));
+        let near = first.cdf_area_distance(&second).unwrap();
+        let far = first.cdf_area_distance(&third).unwrap();
+        assert!((near - 10.0).abs() < 1e-6);
+        assert!((far - 20.0).abs() < 1e-6);
+        assert!((second.cdf_area_distance(&first).unwrap() - near).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_to_openmetrics() {
+        let mut histogram = Histogram::new(20);
+        for i in 1..=10 {
+            histogram.add(i as f64 / 10.0);
+        }
+        let text = histogram.to_openmetrics("request_seconds", &[0.5, 0.25, 1.0, f64::NAN]);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "# TYPE request_seconds histogram");
+        assert_eq!(lines[1], "request_seconds_bucket{le=\"0.25\"} 2");
+        assert_eq!(lines[2], "request_seconds_bucket{le=\"0.5\"} 5");
+        assert_eq!(lines[3], "request_seconds_bucket{le=\"1.0\"} 10");
+        assert_eq!(lines[4], "request_seconds_bucket{le=\"+Inf\"} 10");
+        assert!(lines[5].starts_with("request_seconds_sum 5.5"));
+        assert_eq!(lines[6], "request_seconds_count 10");
+
+        let empty = Histogram::new(10).to_openmetrics("empty", &[]);
+        assert_eq!(
+            empty,
+            "# TYPE empty histogram\nempty_bucket{le=\"+Inf\"} 0\nempty_sum 0.0\nempty_count 0\n"
+        );
+    }
+
+    #[test]
+    fn test_bins_in_range() {
+        let mut histogram = Histogram::new(20);
+        for i in 1..=100 {
+            histogram.add(i as f64);
+        }
+        let window = histogram.bins_in_range(30.0, 60.0);
+        assert!(!window.is_empty());
+        assert!(window.iter().all(|(v, _)| (30.0..=60.0).contains(v)));
+        assert!(window.windows(2).all(|w| w[0].0 < w[1].0));
+        let expected = histogram
+            .iter_bins()
+            .filter(|(v, _)| (30.0..=60.0).contains(v))
+            .count();
+        assert_eq!(window.len(), expected);
+        assert!(histogram.bins_in_range(60.0, 30.0).is_empty());
+        assert_eq!(histogram.bins_in_range(0.0, 1000.0).len(), 20);
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut histogram = Histogram::new(20);
+        for i in 1..=1000 {
+            histogram.add(((i * 37) % 101) as f64);
+        }
+        let frozen = histogram.clone().freeze();
+        assert_eq!(frozen.total(), 1000);
+        assert_eq!(frozen.min(), histogram.min());
+        assert_eq!(frozen.max(), histogram.max());
+        for i in 0..=100 {
+            let q = i as f64 / 100.0;
+            assert_eq!(frozen.quantile(q), histogram.quantile(q));
+            let x = i as f64 + 0.5;
+            assert_eq!(frozen.cdf(x), histogram.cdf(x));
+        }
+        assert_eq!(frozen.quantile(1.5), None);
+        assert_eq!(frozen.rank(-1.0), 0);
+        assert_eq!(frozen.rank(1000.0), 1000);
+
+        let empty = Histogram::new(10).freeze();
+        assert_eq!(empty.quantile(0.5), None);
+        assert_eq!(empty.cdf(0.0), None);
+    }
+
+    #[test]
+    fn test_quantize() {
+        let mut plain = Histogram::new(100);
+        let mut quantized = Histogram::builder().max_bins(100).quantize(0.5).build();
+        for i in 1..=1000 {
+            // a sensor reading around 20 with a little noise
+            let v = 20.0 + ((i * 7919) % 1000) as f64 / 1000.0 - 0.5;
+            plain.add(v);
+            quantized.add(v);
+        }
+        assert_eq!(plain.iter_bins().len(), 100);
+        assert_eq!(quantized.iter_bins().len(), 3);
+        assert!(quantized.iter_bins().all(|(v, _)| v % 0.5 == 0.0));
+        assert!(!quantized.is_exact());
+        assert!((quantized.mean().unwrap() - plain.mean().unwrap()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.expand().count(), 0);
+        for i in 1..=1000 {
+            histogram.add((i % 37) as f64);
+        }
+        assert_eq!(
+            histogram.expand().count() as u64,
+            histogram.report().unwrap().total
+        );
+        let values: Vec<f64> = histogram.expand().collect();
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!((mean - histogram.mean().unwrap()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_modal_interval() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.modal_interval(), None);
+        for i in 1..=1000 {
+            // a triangle peaked at 50
+            let v = (i % 50 + i % 51) as f64;
+            histogram.add(v);
+        }
+        let (low, high) = histogram.modal_interval().unwrap();
+        assert!(low < high);
+        assert!(low <= 50.0 && 50.0 <= high, "{} {}", low, high);
+        let peak = histogram.peak_count().unwrap();
+        let modal = histogram
+            .iter_bins()
+            .find(|(v, _)| (low..=high).contains(v))
+            .unwrap();
+        assert_eq!(modal.1, peak);
+    }
+
+    #[test]
+    fn test_quantile_large_total() {
+        let mut histogram = Histogram::new(10);
+        // 2^54 + 1 is not representable as f64, `q * total as f64` would round it down
+        histogram.add_weighted(1.0, 1 << 54);
+        histogram.add(2.0);
+        assert_eq!(histogram.report().unwrap().total, (1 << 54) + 1);
+        assert_eq!(histogram.quantile(1.0), Some(2.0));
+        assert_eq!(histogram.quantile(0.999999), Some(1.0));
+        assert_eq!(histogram.quantile(0.0), Some(1.0));
+        let frozen = histogram.clone().freeze();
+        assert_eq!(frozen.quantile(1.0), Some(2.0));
+
+        // the float snapping still applies to small totals
+        let mut small = Histogram::new(100);
+        for i in 1..=100 {
+            small.add(i as f64);
+        }
+        assert_eq!(small.quantile(0.07), Some(7.0));
+        assert_eq!(small.quantile(0.071), Some(8.0));
+        assert_eq!(small.quantile(1e-300), Some(1.0));
+    }
+
+    #[test]
+    fn test_num_modes() {
+        let mut histogram = Histogram::new(30);
+        assert_eq!(histogram.num_modes(0.5), 0);
+        for i in 1..=10000 {
+            // two triangles peaked at 20 and 80
+            let offset = ((i * 7) % 10 + (i * 13) % 11) as f64;
+            histogram.add(10.0 + offset);
+            histogram.add(70.0 + offset);
+        }
+        assert_eq!(histogram.num_modes(0.5), 2);
+
+        let mut unimodal = Histogram::new(30);
+        for i in 1..=10000 {
+            unimodal.add(((i * 7) % 10 + (i * 13) % 11) as f64);
+        }
+        assert_eq!(unimodal.num_modes(0.5), 1);
+    }
+
+    #[test]
+    fn test_fuzz_sorted() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        // replays the fuzz seed which left the bins out of order: duplicates, huge
+        // magnitudes whose weighted average overflows, infinities and NaN
+        let pool = [
+            0.0,
+            -0.0,
+            1.0,
+            1.0 + f64::EPSILON,
+            -1.0,
+            1e308,
+            -1e308,
+            f64::MAX,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+            5e-324,
+        ];
+        let mut rng = StdRng::from_seed([158; 32]);
+        let mut histogram = Histogram::new(10);
+        let mut other = Histogram::new(10);
+        for step in 0..5000 {
+            let v = pool[rng.gen_range(0, pool.len())];
+            let count = rng.gen_range(1, 4);
+            match step % 4 {
+                0 => histogram.add(v),
+                1 => histogram.add_weighted(v, count),
+                2 => histogram.buffered().add(v),
+                _ => {
+                    other.add(v);
+                    histogram.merge(&other);
+                }
+            }
+            let values: Vec<f64> = histogram.iter_bins().map(|(v, _)| v).collect();
+            assert!(
+                values.windows(2).all(|w| w[0] <= w[1]),
+                "step {}: {:?}",
+                step,
+                values
+            );
+        }
+        let quantiles: Vec<f64> = (0..=10)
+            .map(|i| histogram.quantile(i as f64 / 10.0).unwrap())
+            .collect();
+        assert!(quantiles.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_quantile_for_count() {
+        let mut histogram = Histogram::new(20);
+        for i in 0..10000 {
+            histogram.add((i % 997) as f64);
+        }
+        for q in &[0.0, 0.1, 0.5, 0.9, 0.95, 0.99, 1.0] {
+            let target = (q * 10000.0) as u64;
+            assert_eq!(histogram.quantile_for_count(target), histogram.quantile(*q));
+        }
+        assert_eq!(histogram.quantile_for_count(10001), None);
+        assert_eq!(Histogram::new(10).quantile_for_count(0), None);
+    }
+
+    #[test]
+    fn test_reservoir_quantile() {
+        use crate::testutil::exact_quantile;
+        use rand::distributions::{Distribution, Exp};
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::from_seed([160; 32]);
+        let exp = Exp::new(1.0);
+        let mut histogram = Histogram::builder().max_bins(50).reservoir(5000).build();
+        let mut other = Histogram::builder().max_bins(50).reservoir(5000).build();
+        let mut values = Vec::new();
+        let mut buffered = other.buffered();
+        for _ in 0..50000 {
+            let (v, w) = (exp.sample(&mut rng), exp.sample(&mut rng));
+            histogram.add(v);
+            buffered.add(w);
+            values.push(v);
+            values.push(w);
+        }
+        drop(buffered);
+        histogram.add_weighted(3.0, 10000);
+        values.extend(std::iter::repeat_n(3.0, 10000));
+        values.sort_by(|a, b| a.total_cmp(b));
+        histogram.merge(&other);
+        assert_eq!(histogram.reservoir.as_ref().unwrap().samples().len(), 5000);
+        for q in &[0.1, 0.5, 0.9, 0.99] {
+            let sampled = histogram.reservoir_quantile(*q).unwrap();
+            let binned = histogram.quantile(*q).unwrap();
+            let exact = exact_quantile(&values, *q);
+            // the sample is random, leave room for its own error
+            assert!((sampled - exact).abs() <= 0.15 * exact, "q={}", q);
+            assert!((sampled - binned).abs() <= 0.05 + 0.1 * sampled, "q={}", q);
+        }
+
+        let mut small = Histogram::builder().reservoir(10).build();
+        assert_eq!(small.reservoir_quantile(0.5), None);
+        small.add(1.0);
+        small.add(3.0);
+        assert_eq!(small.reservoir_quantile(1.0), Some(3.0));
+        assert_eq!(Histogram::new(10).reservoir_quantile(0.5), None);
+    }
+
+    #[test]
+    fn test_merge_range() {
+        let mut histogram = Histogram::new(100);
+        let mut other = Histogram::new(100);
+        for i in 1..=10 {
+            histogram.add(i as f64);
+            other.add(i as f64 * 10.0);
+        }
+        other.add(-1e9);
+        other.add(1e9);
+        other.add(55.0);
+        other.add(1e12);
+        histogram.merge_range(&other, 0.0, 100.0);
+        assert_eq!(histogram.total, 21);
+        assert_eq!(histogram.min(), Some(1.0));
+        assert_eq!(histogram.max(), Some(100.0));
+        assert!(histogram.bins_in_range(-1e12, 0.0).is_empty());
+        assert!(histogram.bins_in_range(101.0, 1e12).is_empty());
+
+        // the extremes of `other` are kept when its outermost bins are merged
+        let mut merged = Histogram::new(10);
+        let mut other = Histogram::new(10);
+        for i in 0..1000 {
+            other.add((i % 100) as f64 + 0.5);
+        }
+        merged.merge_range(&other, f64::NEG_INFINITY, f64::INFINITY);
+        assert_eq!(merged.min(), Some(0.5));
+        assert_eq!(merged.max(), Some(99.5));
+        assert_eq!(merged.total, 1000);
+        assert!(!merged.is_exact());
+
+        // empty anchors are not copied, so the result still round trips
+        let mut anchored = Histogram::new_with_range(10, 0.0, 100.0);
+        anchored.add(55.0);
+        let mut merged = Histogram::new(10);
+        merged.merge_range(&anchored, 0.0, 100.0);
+        assert_eq!(merged.iter_bins().collect::<Vec<_>>(), vec![(55.0, 1)]);
+        let restored = Histogram::from_reader(&merged.to_bytes()[..]).unwrap();
+        assert_eq!(restored.total, 1);
+
+        // a lossy histogram makes the result lossy once its bins are merged
+        let mut exact = Histogram::new(10);
+        exact.add(1.0);
+        let mut downsampled = Histogram::new(10);
+        downsampled.add_weighted(1.0, 2);
+        downsampled.downsample(1);
+        let mut merged = exact.clone();
+        merged.merge_range(&downsampled, 10.0, 20.0);
+        assert!(merged.is_exact());
+        merged.merge_range(&downsampled, 0.0, 20.0);
+        assert!(!merged.is_exact());
+    }
+
+    #[test]
+    fn test_new_with_range() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut histogram = Histogram::new_with_range(20, 0.0, 100.0);
+        assert_eq!(histogram.bins.len(), 20);
+        assert!(histogram.report().is_none());
+        assert_eq!(histogram.quantile(0.5), None);
+        assert_eq!(histogram.modal_interval(), None);
+
+        // the anchors neither improve nor disturb the statistics of the first samples, both
+        // histograms hold them exactly
+        let mut rng = StdRng::from_seed([162; 32]);
+        let mut plain = Histogram::new(20);
+        for _ in 0..5 {
+            let v = rng.gen_range(0.0, 100.0);
+            histogram.add(v);
+            plain.add(v);
+        }
+        for q in &[0.0, 0.25, 0.5, 0.75, 1.0] {
+            assert_eq!(histogram.quantile(*q), plain.quantile(*q));
+        }
+        assert_eq!(histogram.mean(), plain.mean());
+        assert_eq!(histogram.variance(), plain.variance());
+        assert_eq!(histogram.cdf(50.0), plain.cdf(50.0));
+        assert_eq!(histogram.num_modes(0.5), plain.num_modes(0.5));
+        assert_eq!(histogram.bins.len(), 20);
+
+        // nor the interpolated ones, the anchors don't move the bin boundaries
+        for q in &[0.0, 0.1, 0.5, 0.9, 1.0] {
+            assert_eq!(histogram.weighted_quantile(*q), plain.weighted_quantile(*q));
+        }
+        let qs = [0.25, 0.5, 0.75];
+        assert_eq!(
+            histogram.interpolated_percentiles(&qs),
+            plain.interpolated_percentiles(&qs)
+        );
+        for x in &[-1.0, 10.0, 50.0, 90.0, 101.0] {
+            assert_eq!(histogram.ratio_below(*x), plain.ratio_below(*x));
+            assert_eq!(histogram.density(*x), plain.density(*x));
+        }
+        assert_eq!(histogram.modal_interval(), plain.modal_interval());
+        assert_eq!(histogram.decile_mass(), plain.decile_mass());
+        assert!((histogram.decile_mass().iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let labels: Vec<(String, usize)> = histogram
+            .labeled_bins()
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        assert_eq!(labels, plain.labeled_bins());
+        let (lower, upper) = histogram.split_at(50.0);
+        let (plain_lower, plain_upper) = plain.split_at(50.0);
+        assert_eq!(lower.total, plain_lower.total);
+        assert_eq!(upper.total, plain_upper.total);
+        for q in &[0.0, 0.5, 1.0] {
+            assert_eq!(histogram.quantile_interval(*q), plain.quantile_interval(*q));
+        }
+        assert_eq!(histogram.peak_count(), plain.peak_count());
+        assert_eq!(
+            histogram.to_hdr_buckets(1.0, 100.0, 2),
+            plain.to_hdr_buckets(1.0, 100.0, 2)
+        );
+        let frozen = histogram.clone().freeze();
+        for q in &[0.0, 0.5, 1.0] {
+            assert_eq!(frozen.quantile(*q), plain.quantile(*q));
+        }
+        assert_eq!(frozen.rank(50.0), plain.clone().freeze().rank(50.0));
+        assert_eq!(Histogram::new_with_range(10, 0.0, 100.0).peak_count(), None);
+
+        // anchors are dropped before real bins are merged
+        let mut histogram = Histogram::new_with_range(10, 0.0, 100.0);
+        for i in 0..10 {
+            histogram.add(i as f64 * 10.0 + 5.0);
+        }
+        assert!(histogram.is_exact());
+        assert_eq!(histogram.bins.len(), 10);
+        assert!(histogram.iter_bins().all(|(_, count)| count == 1));
+    }
 
     #[test]
-    fn test_histogram() {
+    fn test_ratio_below() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.ratio_below(1.0), None);
+        for i in 1..=1000 {
+            histogram.add(i as f64 / 10.0);
+        }
+        let below = histogram.ratio_below(50.5).unwrap();
+        assert!((below - 0.5).abs() < 0.02, "{}", below);
+        assert_eq!(below + histogram.ratio_above(50.5).unwrap(), 1.0);
+        assert_eq!(histogram.ratio_below(0.0), Some(0.0));
+        assert_eq!(histogram.ratio_below(100.0), Some(1.0));
+        assert_eq!(histogram.ratio_above(1000.0), Some(0.0));
+
+        // unlike cdf the ratio grows smoothly between bin values
+        let mut last = 0.0;
+        for x in 0..=1000 {
+            let ratio = histogram.ratio_below(x as f64 / 10.0).unwrap();
+            assert!(ratio >= last);
+            assert!(ratio - last < 0.01);
+            last = ratio;
+        }
+    }
+
+    #[test]
+    fn test_render_top() {
+        let mut histogram = Histogram::new(100);
+        for i in 0..50 {
+            histogram.add_weighted(i as f64, i % 7 + 1);
+        }
+        let rendered = histogram.render_top(5);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], format!("Total: {}", histogram.total));
+        assert_eq!(lines[6], "... 45 more bins");
+        let values: Vec<f64> = lines[1..6]
+            .iter()
+            .map(|line| line.trim_end_matches('.').parse().unwrap())
+            .collect();
+        assert_eq!(values, vec![6.0, 13.0, 20.0, 27.0, 34.0]);
+
+        assert_eq!(histogram.render_top(50), histogram.to_string());
+        assert_eq!(histogram.render_top(0).lines().count(), 2);
+    }
+
+    #[test]
+    fn test_bucket_percentages() {
         let mut histogram = Histogram::new(10);
-        assert_eq!(histogram.max_bins, 10);
-        histogram.add(1.0);
-        assert_eq!(histogram.total, 1);
-        assert_eq!(histogram.bins.len(), 1);
+        assert!(histogram.bucket_percentages().is_empty());
+        for i in 1..=1000 {
+            histogram.add((i % 37) as f64);
+        }
+        let percentages = histogram.bucket_percentages();
+        assert_eq!(percentages.len(), histogram.bins.len());
+        let sum: f64 = percentages.iter().map(|(_, p)| p).sum();
+        assert!((sum - 100.0).abs() < 1e-9);
 
-        // same value located in same bin
-        histogram.add(1.0);
-        assert_eq!(histogram.total, 2);
-        assert_eq!(histogram.bins.len(), 1);
+        let report = histogram.report().unwrap();
+        let fractions = report.as_fractions();
+        assert_eq!(fractions.first(), Some(&(0.0, report.min)));
+        assert_eq!(fractions.last(), Some(&(1.0, report.max)));
+        assert!(fractions
+            .windows(2)
+            .all(|w| w[0].0 < w[1].0 && w[0].1 <= w[1].1));
+    }
 
-        histogram.add(2.0);
-        assert_eq!(histogram.total, 3);
-        assert_eq!(histogram.bins.len(), 2);
+    #[test]
+    fn test_resample() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.resample(50).total, 0);
+        for i in 0..1000 {
+            histogram.add((i % 100) as f64);
+        }
+        let resampled = histogram.resample(50);
+        assert_eq!(resampled.total, histogram.total);
+        assert_eq!(
+            resampled.iter_bins().map(|(_, c)| c as u64).sum::<u64>(),
+            1000
+        );
+        assert_eq!(resampled.bins.len(), 50);
+        assert!(resampled.is_sorted());
+        assert_eq!(resampled.min(), histogram.min());
+        assert_eq!(resampled.max(), histogram.max());
+        assert!((resampled.mean().unwrap() - histogram.mean().unwrap()).abs() < 1.0);
+        assert!((resampled.quantile(0.5).unwrap() - 50.0).abs() < 3.0);
+
+        let mut single = Histogram::new(10);
+        single.add_weighted(5.0, 7);
+        let resampled = single.resample(20);
+        assert_eq!(resampled.iter_bins().collect::<Vec<_>>(), vec![(5.0, 7)]);
+    }
+
+    #[test]
+    fn test_quick_stats() {
+        let mut histogram = Histogram::new(20);
+        let empty = histogram.quick_stats();
+        assert_eq!(empty.count, 0);
+        assert_eq!(empty.mean, None);
+        assert_eq!(empty.variance, None);
+        assert_eq!(empty.min, None);
+        for i in 1..=10000 {
+            histogram.add(((i * 7919) % 1000) as f64 / 7.0);
+        }
+        let stats = histogram.quick_stats();
+        assert_eq!(stats.count, histogram.total);
+        assert_eq!(stats.min, histogram.min());
+        assert_eq!(stats.max, histogram.max());
+        assert!(approx_eq(
+            stats.mean.unwrap(),
+            histogram.mean().unwrap(),
+            1e-12
+        ));
+        assert!(approx_eq(
+            stats.variance.unwrap(),
+            histogram.variance().unwrap(),
+            1e-9
+        ));
+    }
+
+    #[test]
+    fn test_status() {
+        let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.status(), HistogramStatus::Empty);
+        histogram.add(f64::NAN);
+        assert_eq!(histogram.status(), HistogramStatus::Empty);
+        histogram.add(3.0);
+        assert_eq!(histogram.status(), HistogramStatus::SingleValue);
+        histogram.add_weighted(3.0, 5);
+        assert_eq!(histogram.status(), HistogramStatus::Degenerate);
+        assert_eq!(histogram.variance(), Some(0.0));
+        histogram.add(4.0);
+        assert_eq!(histogram.status(), HistogramStatus::Ready);
+
+        let mut weighted = Histogram::new(10);
+        weighted.add_weighted(1.0, 2);
+        assert_eq!(weighted.status(), HistogramStatus::Degenerate);
+    }
+
+    #[test]
+    fn test_merge_slack() {
+        let mut histogram = Histogram::builder().max_bins(20).merge_slack(10).build();
+        let mut plain = Histogram::new(20);
+        for i in 0..10000 {
+            let v = ((i * 7919) % 1000) as f64;
+            histogram.add(v);
+            plain.add(v);
+            assert!(histogram.bins.len() <= 30);
+        }
+        assert!(histogram.bins.len() > 20);
+        assert_eq!(histogram.total, plain.total);
+        assert!(approx_eq(
+            histogram.mean().unwrap(),
+            plain.mean().unwrap(),
+            1e-9
+        ));
+        for q in &[0.1, 0.5, 0.9, 0.99] {
+            let (slack, exact) = (histogram.quantile(*q).unwrap(), *q * 1000.0);
+            assert!((slack - exact).abs() < 50.0, "q={} {}", q, slack);
+        }
+        histogram.compact();
+        assert!(histogram.bins.len() <= 20);
+    }
+
+    #[test]
+    fn test_cumulative_counts() {
+        let mut histogram = Histogram::new(10);
+        assert!(histogram.cumulative_counts().is_empty());
+        for i in 0..1000 {
+            histogram.add_weighted((i % 97) as f64, i % 5 + 1);
+        }
+        let cumulative = histogram.cumulative_counts();
+        assert_eq!(cumulative.len(), histogram.bins.len());
+        assert!(cumulative.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(cumulative.last(), Some(&histogram.total));
+    }
+
+    #[test]
+    fn test_add_indexed() {
+        let mut histogram = Histogram::new(10);
+        for i in 0..10 {
+            assert_eq!(histogram.add_indexed(i as f64), Some(i));
+        }
+        assert_eq!(histogram.add_indexed(5.0), Some(5));
+        assert_eq!(histogram.add_indexed(-1.0), Some(0));
+        assert_eq!(histogram.add_indexed(f64::NAN), None);
+        // once full every new value lands at the end before the merge moves it
+        for i in 10..100 {
+            assert_eq!(histogram.add_indexed(i as f64), Some(10));
+            assert_eq!(histogram.bins.len(), 10);
+        }
+    }
 
+    #[test]
+    fn test_qq_points() {
+        let mut histogram = Histogram::new(20);
+        assert!(histogram.qq_points(&histogram.clone(), 10).is_empty());
+        for i in 0..1000 {
+            histogram.add(((i * 7919) % 1000) as f64);
+        }
+        let points = histogram.qq_points(&histogram.clone(), 10);
+        assert_eq!(points.len(), 10);
+        assert!(points.iter().all(|(x, y)| x == y));
+        assert!(points.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert_eq!(points.last().map(|p| p.0), histogram.quantile(1.0));
+        assert!(histogram.qq_points(&Histogram::new(10), 10).is_empty());
+        assert!(histogram.qq_points(&histogram.clone(), 0).is_empty());
+    }
+
+    #[test]
+    fn test_top_n_mass() {
         let mut histogram = Histogram::new(10);
+        assert_eq!(histogram.top_n_mass(3), None);
+        for i in 0..100 {
+            histogram.add(i as f64);
+        }
+        histogram.add_weighted(42.0, 900);
+        let top = histogram.top_n_mass(1).unwrap();
+        assert!(top > 0.9, "{}", top);
+        assert!(histogram.top_n_mass(3).unwrap() > top);
+        assert_eq!(histogram.top_n_mass(100), Some(1.0));
+        assert_eq!(histogram.top_n_mass(0), Some(0.0));
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let mut histogram = Histogram::new(100);
+        assert_eq!(histogram.neighbors(1.0), (None, None));
         for i in 1..=100 {
             histogram.add(i as f64);
         }
-        assert_eq!(histogram.total, 100);
+        assert_eq!(
+            histogram.neighbors(50.5),
+            (Some((50.0, 1)), Some((51.0, 1)))
+        );
+        assert_eq!(
+            histogram.neighbors(50.0),
+            (Some((50.0, 1)), Some((51.0, 1)))
+        );
+        assert_eq!(histogram.neighbors(100.0), (Some((100.0, 1)), None));
+        assert_eq!(histogram.neighbors(-1.0), (None, Some((1.0, 1))));
+    }
+
+    #[test]
+    fn test_nearest_bin() {
+        let mut exact = Histogram::new(100);
+        let mut snapped = Histogram::builder().max_bins(100).nearest_bin(0.05).build();
+        for i in 0..10 {
+            for offset in &[-0.01, 0.0, 0.02] {
+                let v = i as f64 + offset;
+                exact.add(v);
+                snapped.add(v);
+            }
+        }
+        assert_eq!(exact.bins.len(), 30);
+        assert_eq!(snapped.bins.len(), 10);
+        assert_eq!(snapped.total, exact.total);
+        assert!(approx_eq(
+            snapped.mean().unwrap(),
+            exact.mean().unwrap(),
+            1e-12
+        ));
+        assert!(approx_eq(
+            snapped.variance().unwrap(),
+            exact.variance().unwrap(),
+            1e-9
+        ));
+        assert!(!snapped.is_exact());
+        assert!(snapped.is_sorted());
+
+        // values further apart than the tolerance keep their own bins
+        snapped.add(4.5);
+        assert_eq!(snapped.bins.len(), 11);
+        assert_eq!(snapped.add_indexed(4.52), Some(5));
+        assert_eq!(snapped.bins.len(), 11);
+    }
+
+    #[test]
+    fn test_effective_sample_size() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.effective_sample_size(), 0.0);
+        for i in 0..20 {
+            histogram.add_weighted(i as f64, 5);
+        }
+        assert!((histogram.effective_sample_size() - 20.0).abs() < 1e-9);
+
+        let mut skewed = Histogram::new(20);
+        for i in 0..20 {
+            skewed.add_weighted(i as f64, 1 << (i / 2));
+        }
+        let ess = skewed.effective_sample_size();
+        assert!(ess > 1.0 && ess < 10.0, "{}", ess);
+    }
+
+    #[test]
+    fn test_replay() {
+        let values: Vec<f64> = (0..1000).map(|i| ((i * 7919) % 1000) as f64).collect();
+        let reports = Histogram::replay(&values, 20, 64);
+        assert_eq!(reports.len(), 16);
+        assert!(reports.windows(2).all(|w| w[0].total < w[1].total));
+
+        let mut histogram = Histogram::new(20);
+        for v in values.iter() {
+            histogram.add(*v);
+        }
+        let last = reports.last().unwrap();
+        assert!(last.approx_eq(&histogram.report().unwrap(), 0.0));
+        assert!(Histogram::replay(&[f64::NAN], 20, 0).is_empty());
+    }
+
+    #[test]
+    fn test_shrink_to() {
+        let mut histogram = Histogram::new(50);
+        for i in 0..1000 {
+            histogram.add((i % 100) as f64);
+        }
+        let mean = histogram.mean();
+        histogram.shrink_to(10);
         assert_eq!(histogram.bins.len(), 10);
-        assert_eq!(histogram.max_bins, 10);
+        assert_eq!(histogram.max_bins, 50);
+        assert_eq!(histogram.total, 1000);
+        assert!(approx_eq(histogram.mean().unwrap(), mean.unwrap(), 1e-12));
+
+        for i in 0..1000 {
+            histogram.add(100.0 + (i % 100) as f64);
+        }
+        assert_eq!(histogram.bins.len(), 50);
+        assert_eq!(histogram.total, 2000);
+        // the new values get the full resolution back
+        let upper = histogram.bins_in_range(100.0, 200.0).len();
+        assert!(upper > 30, "{}", upper);
+
+        histogram.shrink_to(0);
+        assert_eq!(histogram.bins.len(), 1);
     }
 
     #[test]
-    fn test_statistics_function() {
-        let mut histogram = Histogram::new(10);
+    fn test_trimmed_variance() {
+        let mut histogram = Histogram::new(50);
+        for i in 0..1000 {
+            histogram.add((i % 100) as f64);
+        }
+        let clean = histogram.trimmed_variance(0.05).unwrap();
+        assert!(approx_eq(
+            histogram.trimmed_variance(0.0).unwrap(),
+            histogram.variance().unwrap(),
+            1e-12
+        ));
+        assert!(approx_eq(
+            histogram.trimmed_mean(0.0).unwrap(),
+            histogram.mean().unwrap(),
+            1e-12
+        ));
+
+        let mut noisy = histogram.clone();
+        for _ in 0..10 {
+            noisy.add(1e6);
+            noisy.add(-1e6);
+        }
+        assert!(noisy.variance().unwrap() > 1e3 * clean);
+        let trimmed = noisy.trimmed_variance(0.05).unwrap();
+        assert!(
+            (trimmed - clean).abs() < 0.15 * clean,
+            "{} {}",
+            trimmed,
+            clean
+        );
+        assert!((noisy.trimmed_mean(0.05).unwrap() - 49.5).abs() < 1.0);
+
+        // the fraction is clamped below one half, leaving the spread of the median bin
+        let median = noisy.trimmed_variance(0.9).unwrap();
+        assert!(median < 5.0, "{}", median);
+        assert_eq!(Histogram::new(10).trimmed_variance(0.1), None);
+    }
+
+    #[test]
+    fn test_render_normalized() {
+        assert_eq!(Histogram::new(10).render_normalized(10), "");
+        for total in &[10, 100_000] {
+            let mut histogram = Histogram::new(10);
+            for i in 0..*total {
+                histogram.add((i % 7) as f64 + 0.5 * (i % 3) as f64);
+            }
+            let rendered = histogram.render_normalized(40);
+            let heights: Vec<usize> = rendered
+                .lines()
+                .map(|line| line.split_once(' ').unwrap().1.len())
+                .collect();
+            assert_eq!(heights.len(), histogram.bins.len());
+            assert_eq!(heights.iter().max(), Some(&40));
+        }
+    }
+
+    #[test]
+    fn test_preserve_singletons() {
+        let tail = [
+            (200.0, 3),
+            (201.0, 1),
+            (250.0, 3),
+            (252.0, 1),
+            (300.0, 3),
+            (301.5, 1),
+        ];
+        let build = |histogram: &mut Histogram| {
+            for i in 0..10000 {
+                histogram.add((i % 100) as f64);
+            }
+            for (value, count) in tail.iter() {
+                for _ in 0..*count {
+                    histogram.add(*value);
+                }
+            }
+        };
+        let observed = |value: f64| tail.iter().any(|(v, _)| *v == value);
+        let mut preserved = Histogram::builder()
+            .max_bins(10)
+            .preserve_singletons(5.0)
+            .build();
+        let mut averaged = Histogram::new(10);
+        build(&mut preserved);
+        build(&mut averaged);
+
+        let upper: Vec<f64> = preserved
+            .bins_in_range(150.0, 400.0)
+            .iter()
+            .map(|(value, _)| *value)
+            .collect();
+        assert_eq!(upper, vec![200.0, 250.0, 300.0]);
+        assert_eq!(preserved.quantile(1.0), Some(300.0));
+        assert!(!observed(averaged.quantile(1.0).unwrap()));
+        assert_eq!(preserved.total, averaged.total);
+        // the trade-off: the mean moves a little
+        let shift = (preserved.mean().unwrap() - averaged.mean().unwrap()).abs();
+        assert!(
+            shift > 0.0 && shift < 0.01 * averaged.mean().unwrap(),
+            "{}",
+            shift
+        );
+    }
+
+    #[test]
+    fn test_add_and_flag() {
+        let mut histogram = Histogram::new(20);
+        assert!(!histogram.add_and_flag(1000.0, 0.99));
+        let mut flagged = 0;
+        for i in 0..10000 {
+            if histogram.add_and_flag(100.0 + (i % 10) as f64, 0.999) {
+                flagged += 1;
+            }
+        }
+        // only the ramp up while the first baseline values arrive is flagged
+        assert!(flagged < 10, "{}", flagged);
+        assert!(histogram.add_and_flag(150.0, 0.999));
+        assert!(!histogram.add_and_flag(f64::NAN, 0.999));
+        assert_eq!(histogram.total, 10002);
+    }
+
+    #[test]
+    fn test_running_moments() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::from_seed([7; 32]);
+        let mut histogram = Histogram::new(20);
+        for _ in 0..5000 {
+            histogram.add(rng.gen_range(0.0, 1000.0));
+        }
+        let mut other = Histogram::new(20);
+        other.add_weighted(2500.0, 3);
+        for i in 0..100 {
+            other.add(i as f64);
+        }
+        histogram.merge(&other);
+
+        let mean = histogram.centroid().unwrap();
+        let variance = histogram
+            .bins
+            .iter()
+            .map(|i| i.count as f64 * (i.value - mean) * (i.value - mean) + i.m2)
+            .sum::<f64>()
+            / histogram.total as f64;
+        assert!(approx_eq(histogram.mean().unwrap(), mean, 1e-9));
+        assert!(approx_eq(histogram.variance().unwrap(), variance, 1e-9));
+
+        histogram.decay(0.5);
+        let mean = histogram.centroid().unwrap();
+        assert!(approx_eq(histogram.mean().unwrap(), mean, 1e-9));
+    }
+
+    #[test]
+    fn test_overlap() {
+        let mut first = Histogram::new(30);
+        let mut second = Histogram::new(30);
+        for i in 0..1000 {
+            first.add((i % 100) as f64);
+            second.add((i % 100) as f64 + 25.0);
+        }
+        assert!(approx_eq(first.overlap(&first).unwrap(), 1.0, 1e-9));
+        let overlap = first.overlap(&second).unwrap();
+        assert!((overlap - 0.75).abs() < 0.03, "{}", overlap);
+        assert_eq!(overlap, second.overlap(&first).unwrap());
+
+        let mut far = Histogram::new(30);
+        far.add(1000.0);
+        far.add(2000.0);
+        assert_eq!(first.overlap(&far), Some(0.0));
+        assert_eq!(first.overlap(&Histogram::new(10)), None);
+
+        let integral: f64 = (0..990)
+            .map(|i| first.density(i as f64 / 10.0 + 0.05).unwrap() * 0.1)
+            .sum();
+        assert!((integral - 1.0).abs() < 0.01);
+        assert_eq!(first.density(-1.0), Some(0.0));
+    }
+
+    #[test]
+    fn test_insertion_order() {
+        let mut histogram = Histogram::builder()
+            .max_bins(5)
+            .track_insertion_order(true)
+            .build();
+        assert_eq!(histogram.first_value(), None);
+        for v in &[50.0, 1.0, 99.0, 7.0, f64::NAN, 30.0, 20.0, 60.0] {
+            histogram.add(*v);
+        }
+        assert_eq!(histogram.first_value(), Some(50.0));
+        assert_eq!(histogram.last_value(), Some(60.0));
+        assert_eq!(histogram.min(), Some(1.0));
+        assert_eq!(histogram.max(), Some(99.0));
+
+        let mut buffered = histogram.buffered();
+        buffered.add(3.0);
+        buffered.add(4.0);
+        drop(buffered);
+        assert_eq!(histogram.last_value(), Some(4.0));
+
+        let mut other = histogram.empty_like();
+        other.add(-5.0);
+        other.add(500.0);
+        histogram.merge(&other);
+        assert_eq!(histogram.first_value(), Some(50.0));
+        assert_eq!(histogram.last_value(), Some(500.0));
+
+        let mut plain = Histogram::new(5);
+        plain.add(1.0);
+        assert_eq!(plain.first_value(), None);
+    }
+
+    #[test]
+    fn test_interpolated_percentiles() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.interpolated_percentiles(&[0.5]), vec![None]);
+        for i in 0..1000 {
+            histogram.add(((i * 37) % 1000) as f64);
+        }
+        let qs = [0.99, 0.0, 0.5, -0.1, 0.25, 1.0, 0.5, 0.999];
+        let percentiles = histogram.interpolated_percentiles(&qs);
+        assert_eq!(percentiles.len(), qs.len());
+        for (q, percentile) in qs.iter().zip(percentiles) {
+            assert_eq!(percentile, histogram.weighted_quantile(*q), "q = {}", q);
+        }
+        assert_eq!(
+            histogram.interpolated_percentiles(&[]),
+            Vec::<Option<f64>>::new()
+        );
+    }
+
+    #[test]
+    fn test_fill_imbalance() {
+        assert_eq!(Histogram::new(10).fill_imbalance(), None);
+        let mut uniform = Histogram::new(20);
+        let mut concentrated = Histogram::new(20);
+        for i in 0..1000 {
+            uniform.add(i as f64);
+            concentrated.add(if i % 50 == 0 { i as f64 } else { 500.0 });
+        }
+        let even = uniform.fill_imbalance().unwrap();
+        let skewed = concentrated.fill_imbalance().unwrap();
+        assert!(even < 0.5, "{}", even);
+        assert!(skewed > 3.0, "{}", skewed);
+    }
+
+    #[test]
+    fn test_summary_dto() {
+        let mut histogram = Histogram::new(20);
         for i in 1..=100 {
             histogram.add(i as f64);
         }
+        let dto = histogram.summary_dto_with(&[0.5, 1.5, 0.99]);
+        assert_eq!(dto.count, 100);
+        assert_eq!(dto.mean, Some(50.5));
+        assert_eq!((dto.min, dto.max), (Some(1.0), Some(100.0)));
+        assert_eq!(dto.variance, histogram.variance());
+        assert_eq!(
+            dto.percentiles,
+            vec![
+                (0.5, histogram.quantile(0.5).unwrap()),
+                (0.99, histogram.quantile(0.99).unwrap())
+            ]
+        );
+        assert!(Histogram::new(10).summary_dto().percentiles.is_empty());
+    }
 
-        let mean = histogram.mean();
-        assert_eq!(mean.is_none(), false);
-        let mean = mean.unwrap();
-        assert_eq!(mean, 50.5);
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_summary_dto_json() {
+        use crate::SummaryDto;
 
-        let cdf = histogram.cdf(100.0);
-        assert_eq!(cdf.is_none(), false);
-        let cdf = cdf.unwrap();
-        assert_eq!(cdf, 1.0);
-        let cdf = histogram.cdf(50.0);
-        assert_eq!(cdf.is_none(), false);
-        let cdf = cdf.unwrap();
-        assert_eq!(cdf, 0.45);
+        let mut histogram = Histogram::new(20);
+        for i in 1..=4 {
+            histogram.add(i as f64);
+        }
+        let json = serde_json::to_string(&histogram.summary_dto_with(&[0.5])).unwrap();
+        assert_eq!(
+            json,
+            r#"{"count":4,"mean":2.5,"min":1.0,"max":4.0,"variance":1.25,"percentiles":[[0.5,2.0]]}"#
+        );
+        let dto: SummaryDto = serde_json::from_str(&json).unwrap();
+        assert_eq!(dto, histogram.summary_dto_with(&[0.5]));
 
-        let quantile = histogram.quantile(0.50);
-        assert_eq!(quantile.is_none(), false);
-        let quantile = quantile.unwrap();
-        assert_eq!(quantile, 52.5);
+        let empty = serde_json::to_string(&Histogram::new(10).summary_dto()).unwrap();
+        assert_eq!(empty, r#"{"count":0,"percentiles":[]}"#);
+        let dto: SummaryDto = serde_json::from_str(&empty).unwrap();
+        assert_eq!(dto.mean, None);
+    }
 
-        let variance = histogram.variance();
-        assert_eq!(variance.is_none(), false);
-        let variance = variance.unwrap();
-        assert_eq!(variance, 823.765);
+    #[test]
+    fn test_decile_mass() {
+        assert!(Histogram::new(10).decile_mass().is_empty());
+        let mut histogram = Histogram::new(20);
+        for i in 0..=10000 {
+            histogram.add(i as f64 / 100.0);
+        }
+        let mass = histogram.decile_mass();
+        assert_eq!(mass.len(), 10);
+        assert!(mass.iter().all(|m| (m - 0.1).abs() < 0.01), "{:?}", mass);
+        assert!(approx_eq(mass.iter().sum(), 1.0, 1e-9));
+
+        let mut single = Histogram::new(10);
+        single.add_weighted(5.0, 3);
+        let mass = single.decile_mass();
+        assert_eq!(mass[0], 1.0);
+        assert_eq!(mass[1..].iter().sum::<f64>(), 0.0);
     }
+
     #[test]
-    fn test_print() {
-        let mut histogram = Histogram::new(10);
-        for i in 1..=12 {
+    fn test_add_slice() {
+        let values: Vec<f64> = (0..1000).map(|i| ((i * 37) % 1000) as f64).collect();
+        let mut histogram = Histogram::new(20);
+        histogram.add(5.5);
+        histogram.add_slice(&values[..500]);
+        histogram.add_slice(&[f64::NAN]);
+        histogram.add_slice(&values[500..]);
+        assert_eq!(histogram.total, 1001);
+        assert_eq!(histogram.bins.len(), 20);
+        assert_eq!((histogram.min(), histogram.max()), (Some(0.0), Some(999.0)));
+
+        // the slice is sorted first, so its order doesn't matter
+        let mut first = Histogram::new(20);
+        first.add_slice(&values);
+        let mut second = Histogram::new(20);
+        second.add_slice(&values.iter().rev().copied().collect::<Vec<_>>());
+        assert_eq!(
+            first.iter_bins().collect::<Vec<_>>(),
+            second.iter_bins().collect::<Vec<_>>()
+        );
+
+        let mut plain = Histogram::new(20);
+        plain.add(5.5);
+        for v in values.iter() {
+            plain.add(*v);
+        }
+        assert!(approx_eq(
+            histogram.mean().unwrap(),
+            plain.mean().unwrap(),
+            1e-9
+        ));
+        for h in [&histogram, &plain] {
+            let median = h.weighted_quantile(0.5).unwrap();
+            assert!((median - 500.0).abs() < 25.0, "{}", median);
+        }
+    }
+
+    #[test]
+    fn test_survival_batch() {
+        let mut histogram = Histogram::new(20);
+        assert_eq!(histogram.survival_batch(&[1.0]), vec![None]);
+        for i in 0..1000 {
+            histogram.add(((i * 37) % 1000) as f64);
+        }
+        let xs = [500.0, -1.0, 2000.0, 123.4, f64::NAN, 999.0, 500.0];
+        let cdf = histogram.cdf_batch(&xs);
+        let survival = histogram.survival_batch(&xs);
+        for (index, x) in xs.iter().enumerate() {
+            if x.is_nan() {
+                assert_eq!((cdf[index], survival[index]), (None, None));
+                continue;
+            }
+            assert_eq!(cdf[index], histogram.cdf(*x));
+            assert_eq!(survival[index], histogram.survival(*x));
+            assert!(approx_eq(
+                cdf[index].unwrap() + survival[index].unwrap(),
+                1.0,
+                1e-12
+            ));
+        }
+        assert_eq!(survival[1], Some(1.0));
+        assert_eq!(survival[2], Some(0.0));
+    }
+
+    #[test]
+    fn test_conditional_mean_above() {
+        assert_eq!(Histogram::new(10).conditional_mean_above(0.9), None);
+        let mut histogram = Histogram::new(50);
+        // pareto distributed values with a heavy right tail
+        for i in 0..10000 {
+            let u = (i as f64 + 0.5) / 10000.0;
+            histogram.add(1.0 / (1.0 - u).powf(1.0 / 1.5));
+        }
+        let mean = histogram.mean().unwrap();
+        let tail = histogram.conditional_mean_above(0.95).unwrap();
+        assert!(tail > mean);
+        assert!(tail > histogram.quantile(0.95).unwrap());
+        assert!(histogram.conditional_mean_above(0.99).unwrap() > tail);
+        assert!(approx_eq(
+            histogram.conditional_mean_above(0.0).unwrap(),
+            mean,
+            1e-9
+        ));
+        assert_eq!(histogram.conditional_mean_above(1.0), histogram.max());
+        assert_eq!(histogram.conditional_mean_above(1.5), None);
+    }
+
+    #[test]
+    fn test_conditional_mean_below() {
+        assert_eq!(Histogram::new(10).conditional_mean_below(0.1), None);
+        let mut histogram = Histogram::new(50);
+        // exponentially distributed values, skewed to the right
+        for i in 0..10000 {
+            let u = (i as f64 + 0.5) / 10000.0;
+            histogram.add(-(1.0 - u).ln() * 10.0);
+        }
+        let mean = histogram.mean().unwrap();
+        let low = histogram.conditional_mean_below(0.5).unwrap();
+        assert!(low < mean);
+        assert!(low < histogram.quantile(0.5).unwrap());
+        assert!(histogram.conditional_mean_below(0.1).unwrap() < low);
+        assert!(approx_eq(
+            histogram.conditional_mean_below(1.0).unwrap(),
+            mean,
+            1e-9
+        ));
+        assert_eq!(histogram.conditional_mean_below(0.0), histogram.min());
+
+        // both tails split the mean by their weight
+        let above = histogram.conditional_mean_above(0.3).unwrap();
+        let below = histogram.conditional_mean_below(0.3).unwrap();
+        assert!(approx_eq(0.3 * below + 0.7 * above, mean, 1e-9));
+    }
+
+    #[test]
+    fn test_nan_policy() {
+        use crate::NanPolicy;
+
+        let mut reject = Histogram::builder().nan_policy(NanPolicy::Reject).build();
+        reject.add(f64::NAN);
+        reject.add_weighted(f64::NAN, 3);
+        assert!(reject.try_add(f64::NAN).is_ok());
+        assert!(reject.try_add(1.0).is_ok());
+        assert_eq!((reject.total, reject.nan_count()), (1, 0));
+
+        let mut skip = Histogram::builder().nan_policy(NanPolicy::Skip).build();
+        skip.add(f64::NAN);
+        skip.add_weighted(f64::NAN, 3);
+        assert!(skip.try_add(f64::NAN).is_ok());
+        skip.add_slice(&[f64::NAN, 2.0]);
+        assert_eq!((skip.total, skip.nan_count()), (1, 6));
+        assert!(matches!(
+            skip.try_add(f64::INFINITY),
+            Err(HistogramError::InvalidValue(_))
+        ));
+        let mut merged = skip.empty_like();
+        merged.merge(&skip);
+        assert_eq!(merged.nan_count(), 6);
+
+        let mut error = Histogram::builder()
+            .nan_policy(NanPolicy::TreatAsError)
+            .build();
+        error.add(f64::NAN);
+        assert!(matches!(
+            error.try_add_weighted(f64::NAN, 2),
+            Err(HistogramError::InvalidValue(_))
+        ));
+        assert!(error.try_add_weighted(1.0, 2).is_ok());
+        assert_eq!((error.total, error.nan_count()), (2, 0));
+        assert_eq!(Histogram::new(10).nan_policy, NanPolicy::Reject);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut histogram = Histogram::new(20);
+        for i in 0..100 {
             histogram.add(i as f64);
         }
-        println!("{}", histogram.to_string());
+        // fixed value, the fingerprint must not change between runs or versions
+        assert_eq!(histogram.fingerprint(), 0x0ff9_c7e5_a49a_f420);
+
+        let mut same = Histogram::new(20);
+        let mut buffered = same.buffered();
+        for i in (0..100).rev() {
+            buffered.add(i as f64);
+        }
+        drop(buffered);
+        assert_eq!(
+            same.fingerprint() == histogram.fingerprint(),
+            same.iter_bins().eq(histogram.iter_bins())
+        );
+        let restored = Histogram::from_reader(&histogram.to_bytes()[..]).unwrap();
+        assert_eq!(restored.fingerprint(), histogram.fingerprint());
+
+        let before = histogram.fingerprint();
+        histogram.add(50.0);
+        assert_ne!(histogram.fingerprint(), before);
+        assert_ne!(
+            Histogram::new(10).fingerprint(),
+            Histogram::new(20).fingerprint()
+        );
     }
+
     #[test]
-    fn test_report() {
+    fn test_tracked_percentiles() {
+        use crate::testutil::exact_quantile;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::from_seed([198; 32]);
+        let mut histogram = Histogram::builder()
+            .max_bins(200)
+            .merge_slack(50)
+            .track_percentiles(true)
+            .build();
+        assert!(histogram.tracked_percentiles().iter().all(|v| v.is_nan()));
+        let mut values = Vec::new();
+        for _ in 0..5000 {
+            // few distinct values keep the histogram exact
+            let v = rng.gen_range(0, 150) as f64;
+            histogram.add(v);
+            values.push(v);
+            let expected = [0.5, 0.9, 0.99].map(|q| histogram.quantile(q).unwrap());
+            assert_eq!(histogram.tracked_percentiles(), expected);
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(
+            histogram.tracked_percentiles(),
+            [0.5, 0.9, 0.99].map(|q| exact_quantile(&values, q))
+        );
+
+        // merged bins and other changes fall back to a walk over the bins
+        for _ in 0..5000 {
+            histogram.add_weighted(rng.gen::<f64>() * 1000.0, 2);
+            let expected = [0.5, 0.9, 0.99].map(|q| histogram.quantile(q).unwrap());
+            assert_eq!(histogram.tracked_percentiles(), expected);
+        }
+        histogram.decay(0.5);
+        let expected = [0.5, 0.9, 0.99].map(|q| histogram.quantile(q).unwrap());
+        assert_eq!(histogram.tracked_percentiles(), expected);
+        assert_eq!(
+            Histogram::new(10).tracked_percentiles()[0].to_bits(),
+            f64::NAN.to_bits()
+        );
+
+        // dropping several anchors in one add shrinks the bins without a merge
+        let mut histogram = Histogram::builder()
+            .max_bins(20)
+            .merge_slack(10)
+            .track_percentiles(true)
+            .build();
+        let mut anchored = Histogram::new_with_range(20, 0.0, 100.0);
+        anchored.add(50.0);
+        histogram.merge(&anchored);
+        for i in 0..20 {
+            histogram.add(i as f64 * 3.0 + 1.0);
+            let expected = [0.5, 0.9, 0.99].map(|q| histogram.quantile(q).unwrap());
+            assert_eq!(histogram.tracked_percentiles(), expected);
+        }
+    }
+
+    #[test]
+    fn test_labeled_bins() {
+        assert!(Histogram::new(10).labeled_bins().is_empty());
         let mut histogram = Histogram::new(10);
-        for i in 1..=100 {
-            histogram.add(i as f64);
+        for i in 0..100 {
+            histogram.add(i as f64 / 4.0);
+        }
+        let labels = histogram.labeled_bins();
+        assert_eq!(labels.len(), 10);
+        assert_eq!(
+            labels.iter().map(|(_, count)| *count).collect::<Vec<_>>(),
+            histogram
+                .iter_bins()
+                .map(|(_, count)| count)
+                .collect::<Vec<_>>()
+        );
+        assert!(labels[0].0.starts_with("[0.0, "));
+        assert!(labels[9].0.ends_with(", 24.75]"));
+        // neighbouring labels share their boundary
+        for pair in labels.windows(2) {
+            let high = pair[0].0.trim_end_matches(')').split(", ").nth(1).unwrap();
+            assert!(pair[1].0.starts_with(&format!("[{}, ", high)));
         }
-        let report = HistogramReport::new(&histogram);
-        assert_eq!(report.is_none(), false);
-        let report = report.unwrap();
 
-        assert_eq!(report.max, 100.00);
-        assert_eq!(report.min, 1.00);
-        assert_eq!(report.mean, 50.5);
-        assert_eq!(report.total, 100);
-        assert_eq!(report.percent50, 52.5);
-        assert_eq!(report.percent90, 86.0);
-        assert_eq!(report.percent99, 96.5);
+        let mut single = Histogram::new(5);
+        single.add(-2.5);
+        assert_eq!(
+            single.labeled_bins(),
+            vec![(String::from("[-2.5, -2.5]"), 1)]
+        );
+    }
+
+    #[test]
+    fn test_mass_between_quantiles() {
+        assert_eq!(Histogram::new(10).mass_between_quantiles(0.25, 0.75), None);
+        let mut histogram = Histogram::new(20);
+        for i in 0..10000 {
+            histogram.add((i % 1000) as f64);
+        }
+        for (low, high) in [(0.25, 0.75), (0.1, 0.9), (0.0, 1.0), (0.5, 0.5)].iter() {
+            let mass = histogram.mass_between_quantiles(*low, *high).unwrap();
+            // the boundary bins hold about 1/20 of the values each
+            assert!(mass >= high - low - 1e-9, "{} {} {}", low, high, mass);
+            assert!(mass <= high - low + 0.15, "{} {} {}", low, high, mass);
+        }
+        assert_eq!(histogram.mass_between_quantiles(0.0, 1.0), Some(1.0));
+        assert_eq!(histogram.mass_between_quantiles(0.75, 0.25), None);
+        assert_eq!(histogram.mass_between_quantiles(-0.1, 0.5), None);
+    }
+
+    #[test]
+    fn test_repair() {
+        use super::Bin;
+
+        // bins taken over without any checks, as a bulk operation could leave them
+        let unchecked = |pairs: &[(f64, usize)]| {
+            let mut histogram = Histogram::new(10);
+            histogram.bins = pairs.iter().map(|(v, c)| Bin::new(*v, *c)).collect();
+            histogram.total = pairs.iter().map(|(_, c)| *c as u64).sum();
+            histogram
+        };
+        let disordered = [
+            (5.0, 1),
+            (1.0, 2),
+            (f64::NAN, 4),
+            (9.0, 1),
+            (1.0, 1),
+            (3.0, 0),
+            (-2.0, 1),
+        ];
+        let mut histogram = unchecked(&disordered);
+        assert!(!histogram.is_sorted());
+        histogram.repair();
+        assert!(histogram.is_sorted());
+        assert_eq!(
+            histogram.iter_bins().collect::<Vec<_>>(),
+            vec![(-2.0, 1), (1.0, 3), (5.0, 1), (9.0, 1)]
+        );
+        assert_eq!(histogram.total, 6);
+        assert_eq!((histogram.min(), histogram.max()), (Some(-2.0), Some(9.0)));
+        assert_eq!(histogram.mean(), Some(2.5));
+        histogram.add(4.0);
+        assert_eq!(histogram.quantile(0.5), Some(1.0));
+
+        // more bins than max_bins are merged down
+        let many: Vec<(f64, usize)> = (0..100).rev().map(|i| (i as f64, 1)).collect();
+        let mut histogram = unchecked(&many);
+        histogram.repair();
+        assert_eq!(histogram.iter_bins().count(), 10);
+        assert_eq!(histogram.total, 100);
+        assert!(approx_eq(histogram.mean().unwrap(), 49.5, 1e-9));
+
+        // a sorted histogram is left as it is
+        let mut sorted = Histogram::new(20);
+        for i in 0..100 {
+            sorted.add((i * 7 % 100) as f64);
+        }
+        let mut repaired = sorted.clone();
+        repaired.repair();
+        assert_eq!(
+            repaired.iter_bins().collect::<Vec<_>>(),
+            sorted.iter_bins().collect::<Vec<_>>()
+        );
+
+        // from_bins repairs its input, so the histogram is usable right away
+        let mut histogram = Histogram::from_bins(&[(5.0, 1), (1.0, 1)], 10);
+        assert_eq!((histogram.min(), histogram.max()), (Some(1.0), Some(5.0)));
+        histogram.add(3.0);
+        assert_eq!(histogram.quantile(0.5), Some(3.0));
+        let histogram = Histogram::from_bins(&[(f64::NAN, 2), (2.0, 1)], 10);
+        assert_eq!(histogram.min(), Some(2.0));
+        assert_eq!(histogram.mean(), Some(2.0));
+        assert_eq!(
+            Histogram::from_bins(&disordered, 10)
+                .iter_bins()
+                .collect::<Vec<_>>(),
+            vec![(-2.0, 1), (1.0, 3), (5.0, 1), (9.0, 1)]
+        );
     }
 }
@@ -0,0 +1,95 @@
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng};
+
+// Reservoir keeps a uniform random sample of at most `capacity` of the added values
+// with Li's algorithm L: once the reservoir is full, the position of the next value
+// to keep is drawn directly, so skipped values (and weighted counts) cost nothing.
+#[derive(Debug, Clone)]
+pub(crate) struct Reservoir {
+    capacity: usize,
+    samples: Vec<f64>,
+    seen: u64,
+    // position of the next value which replaces a sample, counted from 1
+    next: u64,
+    // largest of `capacity` uniform draws, shrinks as the stream grows
+    w: f64,
+    rng: StdRng,
+}
+
+impl Reservoir {
+    pub(crate) fn new(capacity: usize) -> Reservoir {
+        Reservoir {
+            capacity,
+            samples: Vec::with_capacity(capacity),
+            seen: 0,
+            next: 0,
+            w: 1.0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    // empty_like returns an empty reservoir of the same capacity.
+    pub(crate) fn empty_like(&self) -> Reservoir {
+        Reservoir::new(self.capacity)
+    }
+
+    pub(crate) fn add(&mut self, value: f64, count: usize) {
+        let mut count = count as u64;
+        while count > 0 && self.samples.len() < self.capacity {
+            self.samples.push(value);
+            self.seen += 1;
+            count -= 1;
+            if self.samples.len() == self.capacity {
+                self.skip();
+            }
+        }
+        let end = self.seen.saturating_add(count);
+        while self.next <= end && self.samples.len() == self.capacity {
+            let slot = self.rng.gen_range(0, self.capacity);
+            self.samples[slot] = value;
+            self.skip();
+        }
+        self.seen = end;
+    }
+
+    // merge adds the samples of `other`, each one standing for an equal share of the
+    // values `other` has seen.
+    pub(crate) fn merge(&mut self, other: &Reservoir) {
+        let len = other.samples.len() as u64;
+        for (i, value) in other.samples.iter().enumerate() {
+            let share = other.seen / len + u64::from((i as u64) < other.seen % len);
+            self.add(*value, share.min(usize::MAX as u64) as usize);
+        }
+    }
+
+    // skip draws the position of the next value to keep.
+    fn skip(&mut self) {
+        let k = self.capacity as f64;
+        self.w *= (self.uniform().ln() / k).exp();
+        let gap = (self.uniform().ln() / (1.0 - self.w).ln()).floor();
+        let gap = if gap.is_finite() && gap < u64::MAX as f64 {
+            gap as u64
+        } else {
+            u64::MAX
+        };
+        self.next = self
+            .seen
+            .max(self.next)
+            .saturating_add(gap)
+            .saturating_add(1);
+    }
+
+    // uniform returns a value inside 0.0..1.0 which is never 0, so its logarithm is finite.
+    fn uniform(&mut self) -> f64 {
+        loop {
+            let u: f64 = self.rng.gen();
+            if u > 0.0 {
+                return u;
+            }
+        }
+    }
+
+    pub(crate) fn samples(&self) -> &[f64] {
+        &self.samples
+    }
+}
@@ -0,0 +1,201 @@
+//! Exact reference implementations for validating the accuracy of [`Histogram`](crate::Histogram)
+//! on your own data, available with the `testutil` feature.
+
+use crate::{approx_eq, Histogram};
+
+/// exact_quantile returns the exact q-quantile of the sorted values, using the same
+/// definition as [`Histogram::quantile`](crate::Histogram::quantile): the smallest value
+/// whose cumulative count reaches `q * n` (snapped to an integer when it is only off by float
+/// rounding error). Panics when `sorted_values` is empty.
+/// # Examples
+///
+/// ```
+///  use stream_histogram::testutil::exact_quantile;
+///  let values: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+///  assert_eq!(exact_quantile(&values, 0.5), 50.0);
+/// ```
+pub fn exact_quantile(sorted_values: &[f64], q: f64) -> f64 {
+    assert!(!sorted_values.is_empty(), "exact_quantile of empty values");
+    let target = q * sorted_values.len() as f64;
+    let nearest = target.round();
+    let rank = if (target - nearest).abs() <= nearest.abs() * 4.0 * f64::EPSILON {
+        nearest as usize
+    } else {
+        target.ceil() as usize
+    };
+    let index = rank.max(1).min(sorted_values.len()) - 1;
+    sorted_values[index]
+}
+
+/// assert_histograms_similar panics unless both histograms hold the same total and their mean
+/// and the 0.1, 0.25, 0.5, 0.75, 0.9 and 0.99 quantiles agree within the relative tolerance
+/// `rel_tol`. The panic message lists every statistic which differs with both values.
+/// # Examples
+///
+/// ```
+///  use stream_histogram::Histogram;
+///  use stream_histogram::testutil::assert_histograms_similar;
+///  let mut first = Histogram::new(20);
+///  let mut second = Histogram::new(20);
+///  for i in 1..=1000 {
+///       first.add((i * i) as f64);
+///       second.add((i * i) as f64 * 1.001);
+///  }
+///  assert_histograms_similar(&first, &second, 0.05);
+/// ```
+pub fn assert_histograms_similar(a: &Histogram, b: &Histogram, rel_tol: f64) {
+    const QUANTILES: [f64; 6] = [0.1, 0.25, 0.5, 0.75, 0.9, 0.99];
+    let mut diffs = Vec::new();
+    if a.total != b.total {
+        diffs.push(format!("total: {} != {}", a.total, b.total));
+    }
+    let mut compare = |name: String, x: Option<f64>, y: Option<f64>| {
+        let similar = match (x, y) {
+            (Some(x), Some(y)) => approx_eq(x, y, rel_tol),
+            (x, y) => x == y,
+        };
+        if !similar {
+            diffs.push(format!("{}: {:?} != {:?}", name, x, y));
+        }
+    };
+    compare(String::from("mean"), a.mean(), b.mean());
+    for q in QUANTILES.iter() {
+        compare(format!("quantile({})", q), a.quantile(*q), b.quantile(*q));
+    }
+    if !diffs.is_empty() {
+        panic!(
+            "histograms differ beyond a relative tolerance of {}:\n  {}",
+            rel_tol,
+            diffs.join("\n  ")
+        );
+    }
+}
+
+/// quantile_error_curve builds a histogram of `values` for each of `bin_sizes` and returns
+/// the absolute error of its q-quantile against [`exact_quantile`], in the order of
+/// `bin_sizes`. More bins should give a smaller error, so the curve shows how many bins the
+/// data needs for the required accuracy, and where adding more stops paying off. The bin
+/// sizes are used as given, like [`Histogram::try_new`](crate::Histogram::try_new). NaN values
+/// are left out. Panics when no value is left or a bin size is 0.
+/// # Examples
+///
+/// ```
+///  use stream_histogram::testutil::quantile_error_curve;
+///  let values: Vec<f64> = (1..=1000).map(|i| ((i * 7919) % 1000) as f64).collect();
+///  let curve = quantile_error_curve(&values, &[5, 500], 0.9);
+///  assert!(curve[1] < curve[0]);
+/// ```
+pub fn quantile_error_curve(values: &[f64], bin_sizes: &[usize], q: f64) -> Vec<f64> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let exact = exact_quantile(&sorted, q);
+    bin_sizes
+        .iter()
+        .map(|max_bins| {
+            let mut histogram = Histogram::try_new(*max_bins).expect("invalid bin size");
+            for v in values.iter() {
+                histogram.add(*v);
+            }
+            (histogram.quantile(q).unwrap_or(f64::NAN) - exact).abs()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_histograms_similar, exact_quantile, quantile_error_curve};
+    use crate::Histogram;
+    use rand::distributions::{Distribution, Exp, Normal};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_exact_quantile() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(exact_quantile(&values, 0.0), 1.0);
+        assert_eq!(exact_quantile(&values, 0.25), 1.0);
+        assert_eq!(exact_quantile(&values, 0.26), 2.0);
+        assert_eq!(exact_quantile(&values, 1.0), 4.0);
+        let values: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+        assert_eq!(exact_quantile(&values, 0.07), 7.0);
+    }
+
+    // max_quantile_error returns the largest quantile error of the histogram
+    // relative to the range of the data.
+    fn max_quantile_error(values: &mut [f64], max_bins: usize) -> f64 {
+        let mut histogram = Histogram::new(max_bins);
+        for v in values.iter() {
+            histogram.add(*v);
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let range = values[values.len() - 1] - values[0];
+        let mut max_error: f64 = 0.0;
+        for i in 1..100 {
+            let q = i as f64 / 100.0;
+            let error = (histogram.quantile(q).unwrap() - exact_quantile(values, q)).abs();
+            max_error = max_error.max(error / range);
+        }
+        max_error
+    }
+
+    #[test]
+    fn test_quantile_accuracy() {
+        let mut rng = StdRng::from_seed([7; 32]);
+        let normal = Normal::new(100.0, 15.0);
+        let exp = Exp::new(0.1);
+        for max_bins in [20, 50, 100].iter() {
+            let mut uniform: Vec<f64> = (0..20000).map(|_| rng.gen::<f64>() * 1000.0).collect();
+            let mut normal: Vec<f64> = (0..20000).map(|_| normal.sample(&mut rng)).collect();
+            let mut exp: Vec<f64> = (0..20000).map(|_| exp.sample(&mut rng)).collect();
+            let errors = [
+                max_quantile_error(&mut uniform, *max_bins),
+                max_quantile_error(&mut normal, *max_bins),
+                max_quantile_error(&mut exp, *max_bins),
+            ];
+            println!(
+                "max_bins {}: observed max relative error {:?}",
+                max_bins, errors
+            );
+            for error in errors.iter() {
+                assert!(*error < 1.0 / *max_bins as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_assert_histograms_similar() {
+        let mut first = Histogram::new(20);
+        let mut second = Histogram::new(20);
+        for i in 1..=1000 {
+            first.add((i * i) as f64);
+            second.add((i * i) as f64 * 1.001);
+        }
+        assert_histograms_similar(&first, &second, 0.05);
+        assert_histograms_similar(&Histogram::new(10), &Histogram::new(20), 0.0);
+
+        second.add(1e9);
+        let message = std::panic::catch_unwind(|| assert_histograms_similar(&first, &second, 0.05))
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert!(message.contains("total: 1000 != 1001"), "{}", message);
+        assert!(message.contains("mean: "), "{}", message);
+    }
+
+    #[test]
+    fn test_quantile_error_curve() {
+        let mut rng = StdRng::from_seed([197; 32]);
+        let exp = Exp::new(0.1);
+        let values: Vec<f64> = (0..20000).map(|_| exp.sample(&mut rng)).collect();
+        let bin_sizes = [5, 10, 20, 40, 80, 160, 320];
+        for q in [0.5, 0.9, 0.99].iter() {
+            let curve = quantile_error_curve(&values, &bin_sizes, *q);
+            assert_eq!(curve.len(), bin_sizes.len());
+            // a few bins may hit the quantile by luck, so compare halves of the curve
+            let (coarse, fine) = curve.split_at(curve.len() / 2);
+            let coarse = coarse.iter().sum::<f64>() / coarse.len() as f64;
+            let fine = fine.iter().sum::<f64>() / fine.len() as f64;
+            assert!(fine < coarse, "{:?}", curve);
+        }
+    }
+}
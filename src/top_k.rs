@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+// TopK estimates the most frequent exact values with the space saving algorithm:
+// at most `2 * k` counters are kept, and a new value replaces the smallest counter,
+// inheriting its count. Counts may therefore be overestimated, but every value
+// more frequent than `total / (2 * k)` is guaranteed to be tracked.
+#[derive(Debug, Clone)]
+pub(crate) struct TopK {
+    k: usize,
+    counters: HashMap<u64, usize>,
+}
+
+impl TopK {
+    pub(crate) fn new(k: usize) -> TopK {
+        TopK {
+            k,
+            counters: HashMap::with_capacity(2 * k),
+        }
+    }
+
+    // empty_like returns an empty tracker for the same number of values.
+    pub(crate) fn empty_like(&self) -> TopK {
+        TopK::new(self.k)
+    }
+
+    pub(crate) fn add(&mut self, value: f64, count: usize) {
+        if value.is_nan() {
+            return;
+        }
+        // +0.0 and -0.0 are the same value
+        let key = (value + 0.0).to_bits();
+        if let Some(c) = self.counters.get_mut(&key) {
+            *c = c.saturating_add(count);
+            return;
+        }
+        let mut count = count;
+        if self.counters.len() >= 2 * self.k {
+            let (&min_key, &min_count) = self
+                .counters
+                .iter()
+                .min_by_key(|(key, count)| (**count, **key))
+                .expect("counters are not empty");
+            self.counters.remove(&min_key);
+            count = count.saturating_add(min_count);
+        }
+        self.counters.insert(key, count);
+    }
+
+    pub(crate) fn merge(&mut self, other: &TopK) {
+        for (value, count) in other.top(other.counters.len()) {
+            self.add(value, count);
+        }
+    }
+
+    // top returns up to `n` values with the highest counts, the lower value first
+    // when counts are equal.
+    pub(crate) fn top(&self, n: usize) -> Vec<(f64, usize)> {
+        let mut values: Vec<(f64, usize)> = self
+            .counters
+            .iter()
+            .map(|(key, count)| (f64::from_bits(*key), *count))
+            .collect();
+        values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.total_cmp(&b.0)));
+        values.truncate(n);
+        values
+    }
+
+    pub(crate) fn k(&self) -> usize {
+        self.k
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{target_rank, Bin};
+
+// the quantiles kept by the tracker, p50, p90 and p99
+pub(crate) const TRACKED: [f64; 3] = [0.5, 0.9, 0.99];
+
+// Slot remembers the bin holding a tracked quantile: its value, its count and how many
+// values lie in the bins before it.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    before: u64,
+    value: f64,
+    count: u64,
+}
+
+impl Default for Slot {
+    // an empty histogram has no percentiles
+    fn default() -> Self {
+        Slot {
+            before: 0,
+            value: f64::NAN,
+            count: 0,
+        }
+    }
+}
+
+impl Slot {
+    // holds tells whether the bin still holds the value of the given rank, which is the
+    // first bin with a count whose cumulative count reaches the rank.
+    fn holds(&self, rank: u64) -> bool {
+        self.count > 0 && self.before < rank.max(1) && self.before + self.count >= rank
+    }
+}
+
+// Tracker caches the bins of the tracked percentiles. As long as the bins are not merged,
+// an added value only shifts the counts around the cached bins, so they are updated in O(1)
+// and the bins are only walked when a percentile moves to another bin. `key` is the total,
+// the merge count and the number of bins the cache was computed for, any other change of the
+// histogram makes the cache stale.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tracker {
+    key: (u64, u64, usize),
+    slots: [Slot; 3],
+}
+
+impl Tracker {
+    pub(crate) fn is_fresh(&self, key: (u64, u64, usize)) -> bool {
+        self.key == key
+    }
+
+    // add updates the cached bins after `count` values equal to `value` were added without
+    // merging any bins, then walks the bins when a percentile left its bin.
+    pub(crate) fn add<'a>(
+        &mut self,
+        value: f64,
+        count: usize,
+        bins: impl Iterator<Item = &'a Bin>,
+        key: (u64, u64, usize),
+    ) {
+        let mut moved = false;
+        for (slot, q) in self.slots.iter_mut().zip(TRACKED.iter()) {
+            if value < slot.value {
+                slot.before += count as u64;
+            } else if value == slot.value {
+                slot.count += count as u64;
+            }
+            moved |= !target_rank(*q, key.0).is_some_and(|rank| slot.holds(rank));
+        }
+        if moved {
+            self.refresh(bins, key);
+        } else {
+            self.key = key;
+        }
+    }
+
+    // refresh finds the bins of all tracked percentiles in one walk over the bins.
+    pub(crate) fn refresh<'a>(
+        &mut self,
+        bins: impl Iterator<Item = &'a Bin>,
+        key: (u64, u64, usize),
+    ) {
+        self.key = key;
+        let ranks = TRACKED.map(|q| target_rank(q, key.0).unwrap_or(0));
+        let mut next = 0;
+        let mut before: u64 = 0;
+        let mut last = Slot::default();
+        for i in bins {
+            let count = i.count as u64;
+            if count > 0 {
+                last = Slot {
+                    before,
+                    value: i.value,
+                    count,
+                };
+            }
+            while next < ranks.len() && count > 0 && before.saturating_add(count) >= ranks[next] {
+                self.slots[next] = Slot {
+                    before,
+                    value: i.value,
+                    count,
+                };
+                next += 1;
+            }
+            before = before.saturating_add(count);
+            if next == ranks.len() {
+                return;
+            }
+        }
+        // the ranks were never reached, e.g. after counts saturated: take the last bin
+        // holding values like Histogram::quantile, NaN when there is none
+        for slot in self.slots[next..].iter_mut() {
+            *slot = last;
+        }
+    }
+
+    pub(crate) fn values(&self) -> [f64; 3] {
+        self.slots.map(|slot| slot.value)
+    }
+}